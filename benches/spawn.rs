@@ -0,0 +1,97 @@
+//! Throughput of spawning (and collecting) futures that
+//! complete immediately, measuring the overhead of the
+//! scope itself. Run via `cargo bench`.
+
+// The scopes need an executor to spawn onto.
+#![cfg_attr(not(any(feature = "async-std", feature = "tokio", feature = "smol")), allow(dead_code))]
+
+use std::time::{Duration, Instant};
+
+const SCOPES: usize = 1000;
+const FUTURES: usize = 100;
+const FAN_OUT: usize = 100_000;
+
+/// Average time taken by `f` over a number of runs, after a
+/// warm up run.
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    const RUNS: u32 = 10;
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn report(name: &str, elapsed: Duration, futures: usize) {
+    let per_future = elapsed / futures as u32;
+    println!("{:<30} {:>10.2?} per run, {:>8.2?} per future", name, elapsed, per_future);
+}
+
+#[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))]
+fn main() {
+    eprintln!("the benchmark needs one of the `async-std`, `tokio` or `smol` features");
+}
+
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+fn main() {
+    let data: Vec<usize> = (0..FUTURES).collect();
+
+    let elapsed = measure(|| {
+        for _ in 0..SCOPES {
+            let (_, outputs) = async_scoped::scope_and_block(|s| {
+                for i in &data {
+                    s.spawn(async move { *i });
+                }
+            });
+            assert_eq!(outputs.len(), FUTURES);
+        }
+    });
+    report("spawn", elapsed, SCOPES * FUTURES);
+
+    let elapsed = measure(|| {
+        for _ in 0..SCOPES {
+            let (_, outputs) = async_scoped::scope_and_block(|s| {
+                for i in &data {
+                    s.spawn_cancellable(async move { *i }, || 0);
+                }
+            });
+            assert_eq!(outputs.len(), FUTURES);
+        }
+    });
+    report("spawn_cancellable", elapsed, SCOPES * FUTURES);
+
+    let elapsed = measure(|| {
+        for _ in 0..SCOPES {
+            let (_, outputs) = async_scoped::scope_and_block(|s| {
+                s.spawn(async { 0 });
+            });
+            assert_eq!(outputs.len(), 1);
+        }
+    });
+    report("scope (one future)", elapsed, SCOPES);
+
+    // A single scope with a large fan-out, with and without
+    // presizing the collection of spawned futures.
+    let data: Vec<usize> = (0..FAN_OUT).collect();
+    for &(name, window) in &[("fan-out", None), ("fan-out (windowed)", Some(FUTURES))] {
+        for &presize in &[false, true] {
+            let elapsed = measure(|| {
+                let mut s = unsafe { async_scoped::Scope::create() };
+                if let Some(window) = window {
+                    s = s.with_ordered_window(window);
+                }
+                if presize {
+                    s = s.with_initial_capacity(FAN_OUT);
+                }
+                for i in &data {
+                    s.spawn(async move { *i });
+                }
+                let outputs = futures::executor::block_on(s.collect());
+                assert_eq!(outputs.len(), FAN_OUT);
+            });
+            let name = if presize { format!("{}, presized", name) } else { name.to_string() };
+            report(&name, elapsed, FAN_OUT);
+        }
+    }
+}
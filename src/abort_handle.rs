@@ -0,0 +1,34 @@
+use std::sync::{Arc, Weak};
+
+use crate::{Cancellation, CancelReason};
+
+/// A handle to abort the futures of a `Scope`, detached from
+/// the scope itself: it may be cloned, and moved to other
+/// tasks or threads, say a signal handler. Created by
+/// `Scope::abort_handle`.
+#[derive(Clone)]
+pub struct ScopeAbortHandle {
+    cancellation: Weak<Cancellation>,
+}
+
+impl ScopeAbortHandle {
+    pub(crate) fn new(cancellation: &Arc<Cancellation>) -> Self {
+        ScopeAbortHandle { cancellation: Arc::downgrade(cancellation) }
+    }
+
+    /// Abort the cancellable futures of the scope, as in
+    /// `Scope::abort_all`. This has no effect once the
+    /// scope, and its futures, have been dropped.
+    pub fn abort(&self) {
+        if let Some(cancellation) = self.cancellation.upgrade() {
+            cancellation.signal(CancelReason::ExternalSignal);
+        }
+    }
+
+    /// Whether the scope has been aborted (or cancelled).
+    /// Returns `false` once the scope has been dropped.
+    pub fn is_aborted(&self) -> bool {
+        self.cancellation.upgrade()
+            .is_some_and(|cancellation| cancellation.is_signalled())
+    }
+}
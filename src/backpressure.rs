@@ -0,0 +1,18 @@
+/// The state of a `Scope` whose spawn has to wait for its
+/// concurrency limit, as passed to the hook of
+/// `Scope::throttle_on_backpressure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureStats {
+    /// Number of futures being driven.
+    pub in_flight: usize,
+
+    /// Total weight of the futures being driven; see
+    /// `Scope::spawn_weighted`.
+    pub weight: usize,
+
+    /// Weight of the future waiting to be spawned.
+    pub waiting: usize,
+
+    /// The concurrency limit that blocked the spawn.
+    pub limit: usize,
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{Scope, Indexed, Spawner, DropPolicy, Fairness};
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+use crate::DefaultSpawner;
+
+/// Configures a `Scope` before it is created; see
+/// `Scope::builder`. Each setter corresponds to a `with_*`
+/// method of `Scope`, and the values are checked together
+/// by `build`, rather than each panicking on its own.
+pub struct ScopeBuilder<'a, T,
+                        #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                        #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    spawner: Sp,
+    concurrency_limit: Option<usize>,
+    rate_limit: Option<f64>,
+    drop_policy: DropPolicy,
+    fairness: Fairness,
+    catch_panics: bool,
+    order: Option<Order>,
+    conflicting_order: bool,
+    capacity: usize,
+    _output: PhantomData<fn() -> T>,
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>,
+}
+
+/// The order in which the outputs are yielded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Order {
+    Unordered,
+    Ordered,
+    Window(usize),
+}
+
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+impl<'a, T: Send + 'static> ScopeBuilder<'a, T> {
+    pub(crate) fn new() -> Self {
+        ScopeBuilder::with_spawner(DefaultSpawner::default())
+    }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> ScopeBuilder<'a, T, Sp> {
+    /// A builder of a scope that spawns futures using
+    /// `spawner`, as in `Scope::create_with`.
+    pub fn with_spawner(spawner: Sp) -> Self {
+        ScopeBuilder {
+            spawner,
+            concurrency_limit: None,
+            rate_limit: None,
+            drop_policy: DropPolicy::default(),
+            fairness: Fairness::default(),
+            catch_panics: true,
+            order: None,
+            conflicting_order: false,
+            capacity: 0,
+            _output: PhantomData,
+            _marker: PhantomData,
+        }
+    }
+
+    /// See `Scope::with_concurrency_limit`.
+    pub fn concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency_limit = Some(limit);
+        self
+    }
+
+    /// See `Scope::with_rate_limit`.
+    pub fn rate_limit(mut self, per_second: f64) -> Self {
+        self.rate_limit = Some(per_second);
+        self
+    }
+
+    /// See `Scope::with_drop_policy`.
+    pub fn drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// See `Scope::with_fairness`.
+    pub fn fairness(mut self, fairness: Fairness) -> Self {
+        self.fairness = fairness;
+        self
+    }
+
+    /// See `Scope::catch_panics`.
+    pub fn catch_panics(mut self, catch: bool) -> Self {
+        self.catch_panics = catch;
+        self
+    }
+
+    /// Yield the outputs in order of completion. This is
+    /// the default, and conflicts with `ordered` and
+    /// `ordered_window`.
+    pub fn unordered(self) -> Self {
+        self.order(Order::Unordered)
+    }
+
+    /// See `Scope::with_ordering`.
+    pub fn ordered(self) -> Self {
+        self.order(Order::Ordered)
+    }
+
+    /// See `Scope::with_ordered_window`.
+    pub fn ordered_window(self, window: usize) -> Self {
+        self.order(Order::Window(window))
+    }
+
+    /// See `Scope::with_initial_capacity`.
+    pub fn initial_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    fn order(mut self, order: Order) -> Self {
+        if self.order.is_some_and(|prev| prev != order) {
+            self.conflicting_order = true;
+        }
+        self.order = Some(order);
+        self
+    }
+
+    /// Create the configured scope, unless the options are
+    /// invalid: zero limits, or conflicting orders.
+    ///
+    /// # Safety
+    ///
+    /// The same considerations as `Scope::create` apply.
+    pub unsafe fn build(self) -> Result<Scope<'a, T, Sp>, BuildError> {
+        if self.conflicting_order {
+            return Err(BuildError::ConflictingOrder);
+        }
+        if self.concurrency_limit == Some(0) {
+            return Err(BuildError::ZeroConcurrencyLimit);
+        }
+        if self.order == Some(Order::Window(0)) {
+            return Err(BuildError::ZeroWindow);
+        }
+        if self.rate_limit.is_some_and(|per_second| !per_second.is_finite() || per_second <= 0.) {
+            return Err(BuildError::InvalidRateLimit);
+        }
+
+        let mut scope = Scope::create_with(self.spawner);
+        match self.order {
+            None | Some(Order::Unordered) => {},
+            Some(Order::Ordered) => scope = scope.with_ordering(),
+            Some(Order::Window(window)) => scope = scope.with_ordered_window(window),
+        }
+        if let Some(limit) = self.concurrency_limit {
+            scope.set_concurrency_limit(limit);
+        }
+        if let Some(per_second) = self.rate_limit {
+            scope.set_rate_limit(per_second);
+        }
+        scope.catch_panics(self.catch_panics);
+        Ok(scope.with_drop_policy(self.drop_policy)
+                .with_fairness(self.fairness)
+                .with_initial_capacity(self.capacity))
+    }
+
+    /// Similar to `build`, but the outputs are yielded
+    /// along with their index; see `Scope::with_indexing`.
+    ///
+    /// # Safety
+    ///
+    /// The same considerations as `Scope::create` apply.
+    pub unsafe fn build_indexed(self) -> Result<Indexed<'a, T, Sp>, BuildError> {
+        self.build().map(Scope::with_indexing)
+    }
+}
+
+/// The error returned by `ScopeBuilder::build` for invalid
+/// options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// More than one of `unordered`, `ordered`, and
+    /// `ordered_window` was chosen.
+    ConflictingOrder,
+    /// The concurrency limit is zero.
+    ZeroConcurrencyLimit,
+    /// The window of `ordered_window` is zero.
+    ZeroWindow,
+    /// The rate limit is not positive, or not finite.
+    InvalidRateLimit,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::ConflictingOrder => write!(f, "conflicting output orders"),
+            BuildError::ZeroConcurrencyLimit => write!(f, "concurrency limit must be positive"),
+            BuildError::ZeroWindow => write!(f, "ordered window must be positive"),
+            BuildError::InvalidRateLimit => write!(f, "rate limit must be positive and finite"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
@@ -1,29 +1,50 @@
 use std::{
     future::Future, pin::Pin, sync::Arc,
-    task::{Poll, Context}};
+    task::{Poll, Context}, time::Duration};
+use futures_timer::Delay;
 use pin_project::pin_project;
-use crate::Cancellation;
+use crate::cancellation::{Cancellation, CancelReason, Live, Targeted};
 
 /// A wrapper for `Future` to make it cancellable from the
 /// scope that spawned it. The future may be cancelled by
-/// calling `cancel` method or dropping the `Scope`.
+/// calling `cancel` method or dropping the `Scope`; the
+/// default value is then obtained from the reason.
 #[pin_project]
-pub struct CancellableFuture<I, F: Future<Output=I>, Fu: FnOnce() -> I> {
+pub struct CancellableFuture<I, F: Future<Output=I>, Fu: FnOnce(CancelReason) -> I> {
     key: Option<usize>,
     cancellation: Arc<Cancellation>,
     default: Option<Fu>,
+    target: Option<Targeted>,
+    deadline: Option<Delay>,
     #[pin]
     fut: F,
+    // Dropped after `fut`, marking the end of its cleanup
+    _live: Live,
 }
 
-impl<I, F: Future<Output=I>, Fu: FnOnce() -> I> CancellableFuture<I, F, Fu> {
+impl<I, F: Future<Output=I>, Fu: FnOnce(CancelReason) -> I> CancellableFuture<I, F, Fu> {
     pub fn new(cancellation: Arc<Cancellation>,
                fut: F, default: Fu) -> Self {
-        CancellableFuture{key: None, cancellation, fut, default: Some(default)}
+        let _live = cancellation.enter();
+        CancellableFuture{key: None, cancellation, fut, default: Some(default), target: None, deadline: None, _live}
+    }
+
+    /// Allow cancelling this future alone, via
+    /// `Cancellation::cancel_one(index)`.
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.target = Some(self.cancellation.target(index));
+        self
+    }
+
+    /// Cancel this future alone if it does not complete
+    /// within `dur`, with `CancelReason::Timeout`.
+    pub fn with_deadline(mut self, dur: Duration) -> Self {
+        self.deadline = Some(Delay::new(dur));
+        self
     }
 }
 
-impl<I, F: Future<Output=I>, Fu: FnOnce() -> I> Future
+impl<I, F: Future<Output=I>, Fu: FnOnce(CancelReason) -> I> Future
     for CancellableFuture<I, F, Fu>
 {
 
@@ -33,12 +54,67 @@ impl<I, F: Future<Output=I>, Fu: FnOnce() -> I> Future
 
         let this = self.project();
 
+        if let Some(target) = this.target {
+            if target.poll_cancelled(cx) {
+                *this.target = None;
+                this.cancellation.mark_cancelled();
+                return Poll::Ready(this.default.take().unwrap()(CancelReason::Individual));
+            }
+        }
+
         if let Some((result, new_key)) = this.cancellation.poll_future(*this.key, this.fut, cx) {
             *this.key = new_key;
+            if result.is_ready() {
+                *this.target = None;
+            } else if let Some(deadline) = this.deadline {
+                if Pin::new(deadline).poll(cx).is_ready() {
+                    *this.target = None;
+                    this.cancellation.mark_cancelled();
+                    return Poll::Ready(this.default.take().unwrap()(CancelReason::Timeout));
+                }
+            }
             result
         } else {
-            Poll::Ready(this.default.take().unwrap()())
+            *this.target = None;
+            this.cancellation.mark_cancelled();
+            Poll::Ready(this.default.take().unwrap()(this.cancellation.reason()))
         }
     }
 
 }
+
+/// A wrapper for `Future` that races it against a `cancel`
+/// future, yielding the output of whichever resolves first.
+/// If `cancel` resolves first, the future is dropped and
+/// accounted as cancelled by the scope.
+#[pin_project]
+pub struct WithCancel<F, C> {
+    cancellation: Arc<Cancellation>,
+    #[pin]
+    fut: F,
+    #[pin]
+    cancel: C,
+}
+
+impl<I, F: Future<Output=I>, C: Future<Output=I>> WithCancel<F, C> {
+    pub fn new(cancellation: Arc<Cancellation>, fut: F, cancel: C) -> Self {
+        WithCancel { cancellation, fut, cancel }
+    }
+}
+
+impl<I, F: Future<Output=I>, C: Future<Output=I>> Future for WithCancel<F, C> {
+    type Output = I;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context)
+            -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(output) = this.fut.poll(cx) {
+            return Poll::Ready(output);
+        }
+        let poll = this.cancel.poll(cx);
+        if poll.is_ready() {
+            this.cancellation.mark_cancelled();
+        }
+        poll
+    }
+}
@@ -1,30 +1,81 @@
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::task::{Poll, Waker, Context};
 use std::pin::Pin;
 use std::future::Future;
-use async_std::sync::RwLock;
+use async_lock::RwLock;
 use slab::Slab;
 
+/// Why the futures of a scope were cancelled, as passed to
+/// the closures of `Scope::spawn_cancellable_with_reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CancelReason {
+    /// The scope was dropped before its futures completed.
+    ScopeDropped,
+    /// The scope was cancelled via `cancel` (or
+    /// `cancel_all`, `abort_all`), say by the `scope_and_*`
+    /// functions that stop early.
+    AbortAll,
+    /// The deadline of `graceful_shutdown` (or
+    /// `collect_timeout`) elapsed, or that of this future
+    /// alone, via `spawn_timeout_with_reason`.
+    Timeout,
+    /// The scope was aborted via a `ScopeAbortHandle`.
+    ExternalSignal,
+    /// Another future of the scope panicked; see
+    /// `Scope::with_abort_on_panic`.
+    Panic,
+    /// The future alone was cancelled, via
+    /// `Scope::cancel_one`.
+    Individual,
+}
+
 pub struct Cancellation {
+    reason: OnceLock<CancelReason>,
     flag: RwLock<bool>,
+    signalled: AtomicBool,
+    cancelled: AtomicUsize,
     read_wakers: Mutex<Slab<Waker>>,
+    live: AtomicUsize,
+    idle_wakers: Mutex<Vec<Waker>>,
+    targets: Mutex<HashMap<usize, Target>>,
+}
+
+/// A pending cancellable future that may be cancelled
+/// individually, by index.
+#[derive(Default)]
+struct Target {
+    cancelled: bool,
+    waker: Option<Waker>,
 }
 
 impl Cancellation {
     pub fn new() -> Self {
         Cancellation {
+            reason: OnceLock::new(),
             flag: RwLock::new(false),
+            signalled: AtomicBool::new(false),
+            cancelled: AtomicUsize::new(0),
             read_wakers: Mutex::new(Slab::new()),
+            live: AtomicUsize::new(0),
+            idle_wakers: Mutex::new(Vec::new()),
+            targets: Mutex::new(HashMap::new()),
         }
     }
 
-    /// Trigger cancellation: set lock to false and wake all
-    /// futures registered with us.
-    pub async fn cancel(&self) {
+    /// Trigger cancellation: set lock to true and wake all
+    /// futures registered with us. Once this returns, none
+    /// of the futures are being polled, or will be polled
+    /// again.
+    pub async fn cancel(&self, reason: CancelReason) {
+        let _ = self.reason.set(reason);
+
         // Mark scope as being cancelled.
         let mut lock = self.flag.write().await;
         if *lock { return; }
         *lock = true;
+        self.signalled.store(true, Ordering::SeqCst);
 
         // At this point, the read_wakers list is stable.
         // No more wakers could be added any more (as the flag is set).
@@ -34,6 +85,112 @@ impl Cancellation {
         }
     }
 
+    /// Signal cancellation without waiting for futures
+    /// being polled concurrently: all futures registered
+    /// with us are woken, and are cancelled on their next
+    /// poll.
+    pub fn signal(&self, reason: CancelReason) {
+        let _ = self.reason.set(reason);
+        if self.signalled.swap(true, Ordering::SeqCst) { return; }
+
+        // Polls in progress may still use their keys, hence
+        // we do not drain the list here.
+        let list = self.read_wakers.lock().unwrap();
+        for (_, v) in list.iter() {
+            v.wake_by_ref();
+        }
+    }
+
+    /// Whether cancellation has been signalled (or
+    /// triggered).
+    pub fn is_signalled(&self) -> bool {
+        self.signalled.load(Ordering::SeqCst)
+    }
+
+    /// Why the cancellation was triggered (or signalled):
+    /// the reason of the first trigger. Only meaningful once
+    /// it has been.
+    pub fn reason(&self) -> CancelReason {
+        self.reason.get().copied().unwrap_or(CancelReason::AbortAll)
+    }
+
+    /// Number of futures that were cancelled (rather than
+    /// having run to completion).
+    pub fn cancelled(&self) -> usize {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Record that a future was cancelled.
+    pub fn mark_cancelled(&self) {
+        self.cancelled.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Number of cancellable futures alive.
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    /// Register a cancellable future as alive. The returned
+    /// guard should be dropped once the future has been
+    /// dropped.
+    pub fn enter(self: &Arc<Self>) -> Live {
+        self.live.fetch_add(1, Ordering::SeqCst);
+        Live(self.clone())
+    }
+
+    /// Ready once no cancellable futures are alive.
+    pub fn poll_idle(&self, cx: &mut Context) -> Poll<()> {
+        let mut wakers = self.idle_wakers.lock().unwrap();
+        if self.live.load(Ordering::SeqCst) == 0 {
+            return Poll::Ready(());
+        }
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// Register the pending future of index `index`, to be
+    /// cancelled via `cancel_one`. The returned guard
+    /// should be dropped once the future completes.
+    pub fn target(self: &Arc<Self>, index: usize) -> Targeted {
+        self.targets.lock().unwrap().insert(index, Target::default());
+        Targeted(self.clone(), index)
+    }
+
+    /// Cancel the future of index `index` alone, waking it.
+    /// Returns whether it was registered, and still pending
+    /// (and not yet cancelled).
+    pub fn cancel_one(&self, index: usize) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        let waker = match targets.get_mut(&index) {
+            Some(target) if !target.cancelled => {
+                target.cancelled = true;
+                target.waker.take()
+            }
+            _ => return false,
+        };
+        std::mem::drop(targets);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        true
+    }
+
+    /// Whether the future of index `index` was cancelled via
+    /// `cancel_one`; otherwise, registers the waker from
+    /// `cx` to be woken if it is.
+    fn poll_target(&self, index: usize, cx: &mut Context) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        match targets.get_mut(&index) {
+            Some(target) if !target.cancelled => {
+                target.waker = Some(cx.waker().clone());
+                false
+            }
+            _ => true,
+        }
+    }
+
     /// Poll a future if cancellation has not happened. If
     /// polled, the poll result is returned; otherwise, the
     /// cancellation has been triggerred, and this method
@@ -44,8 +201,9 @@ impl Cancellation {
         fut: Pin<&mut F>, cx: &mut Context,
     ) -> Option<(Poll<I>, Option<usize>)> {
 
+        if self.is_signalled() { return None; }
         if let Some(guard) = self.flag.try_read() {
-            if *guard {
+            if !*guard {
                 let poll_result = fut.poll(cx);
 
                 // Add the waker from context into read_wakers list
@@ -73,6 +231,12 @@ impl Cancellation {
                 // Ensure we drop read guard only after adding waker to list
                 std::mem::drop(map);
                 std::mem::drop(guard);
+
+                // A concurrent signal may have missed our
+                // waker: ensure we are polled again.
+                if poll_result.is_pending() && self.is_signalled() {
+                    cx.waker().wake_by_ref();
+                }
                 return Some((poll_result, key));
             }
         }
@@ -81,3 +245,47 @@ impl Cancellation {
 
     }
 }
+
+/// Guard marking a cancellable future as alive.
+pub struct Live(Arc<Cancellation>);
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        if self.0.live.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let wakers = std::mem::take(&mut *self.0.idle_wakers.lock().unwrap());
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Guard marking a cancellable future as targetable by
+/// `cancel_one`, until dropped.
+pub struct Targeted(Arc<Cancellation>, usize);
+
+impl Targeted {
+    /// Whether the future was cancelled via `cancel_one`;
+    /// see `Cancellation::cancel_one`.
+    pub fn poll_cancelled(&self, cx: &mut Context) -> bool {
+        self.0.poll_target(self.1, cx)
+    }
+}
+
+impl Drop for Targeted {
+    fn drop(&mut self) {
+        self.0.targets.lock().unwrap().remove(&self.1);
+    }
+}
+
+/// Guard that signals cancellation if dropped while
+/// panicking, say, as the future holding it panics.
+pub struct AbortOnPanic(pub Arc<Cancellation>);
+
+impl Drop for AbortOnPanic {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.signal(CancelReason::Panic);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::{future::Future, pin::Pin, sync::Arc, task::{Poll, Context}};
+use pin_project::pin_project;
+
+/// The context of a scope, as set via `Scope::set_context`.
+pub(crate) type ScopeContext = Arc<dyn Any + Send + Sync>;
+
+thread_local! {
+    static CURRENT: RefCell<Option<ScopeContext>> = const { RefCell::new(None) };
+}
+
+/// The context of the scope that spawned the future being
+/// polled on this thread, as set via `Scope::set_context`.
+/// Returns `None` outside of the futures spawned in a
+/// scope, if the scope has no context, or if its context is
+/// not of type `C`.
+///
+/// The context is visible only while the spawned future
+/// itself is polled: not in the futures it spawns onto
+/// the executor directly, nor in blocking closures.
+pub fn current_context<C: Clone + 'static>() -> Option<C> {
+    CURRENT.with(|current| {
+        current.borrow().as_ref()
+            .and_then(|context| context.downcast_ref::<C>())
+            .cloned()
+    })
+}
+
+/// A wrapper for `Future` that makes `context` current
+/// while the future is polled.
+#[pin_project]
+pub(crate) struct WithContext<F> {
+    context: Option<ScopeContext>,
+    #[pin]
+    fut: F,
+}
+
+impl<F> WithContext<F> {
+    pub fn new(context: Option<ScopeContext>, fut: F) -> Self {
+        WithContext { context, fut }
+    }
+}
+
+impl<F: Future> Future for WithContext<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.context.is_none() {
+            return this.fut.poll(cx);
+        }
+
+        /// Restores the previous context, even if `fut`
+        /// panics.
+        struct Restore<'c> {
+            context: &'c mut Option<ScopeContext>,
+            previous: Option<ScopeContext>,
+        }
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                *self.context = CURRENT.with(|current| current.replace(self.previous.take()));
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.replace(this.context.take()));
+        let _restore = Restore { context: this.context, previous };
+        this.fut.poll(cx)
+    }
+}
@@ -0,0 +1,25 @@
+/// What a `Scope` does with its pending futures when it is
+/// dropped before being driven to completion. In any case,
+/// the current thread is blocked until no spawned
+/// future is being driven, as required for soundness.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Cancel the cancellable futures (see
+    /// `Scope::spawn_cancellable`), and wait for the other
+    /// futures to complete. This is the default.
+    #[default]
+    CancelAll,
+
+    /// Wait for all the futures to complete, including the
+    /// cancellable ones, similar to `scope_and_block`.
+    BlockOnFinish,
+
+    /// Cancel the cancellable futures (as in `CancelAll`),
+    /// and abort the others (as in `ScopeHandle::abort`):
+    /// each is dropped the next time it is polled. This
+    /// bounds how long the drop blocks, at the cost of the
+    /// futures not running to completion. The futures
+    /// spawned via `spawn_blocking` can not be aborted, and
+    /// are still waited for.
+    AbortAll,
+}
@@ -0,0 +1,18 @@
+/// Which side a `Scope` favours when its concurrency limit
+/// frees up: the spawns waiting for capacity (via
+/// `spawn_limited` and the like), or the consumer of the
+/// outputs of the futures that freed it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Fairness {
+    /// A spawn that had to wait for capacity yields to the
+    /// executor once before spawning, giving the consumer
+    /// (if on another task) a chance to take the outputs
+    /// first. This lowers the latency of the outputs. This
+    /// is the default.
+    #[default]
+    PreferDrain,
+
+    /// A spawn that had to wait for capacity spawns as soon
+    /// as it is woken, keeping the executor busy.
+    PreferSpawn,
+}
@@ -0,0 +1,391 @@
+use std::{
+    future::Future, pin::Pin, sync::{Arc, Mutex},
+    marker::PhantomData, panic::AssertUnwindSafe,
+    task::{Poll, Context, Waker}};
+use futures::future::{AbortHandle, AbortRegistration, Abortable, Aborted, FutureExt, Pending};
+use futures::task::AtomicWaker;
+use pin_project::pin_project;
+use crate::CaughtPanic;
+
+/// The output of a spawned future, or the panic caught
+/// while driving it.
+pub(crate) type Output<T> = Result<T, CaughtPanic>;
+
+/// An output tagged with the index (in order of spawning)
+/// of the future.
+pub(crate) type IndexedOutput<T> = (usize, Output<T>);
+
+/// Outputs of tasks whose handle was dropped after it
+/// received the output, and after the `Scope` stream
+/// skipped past the task. These are yielded by the stream
+/// as soon as possible.
+pub(crate) struct Orphans<T> {
+    outputs: Mutex<Vec<IndexedOutput<T>>>,
+    waker: AtomicWaker,
+}
+
+impl<T> Orphans<T> {
+    pub fn new() -> Self {
+        Orphans {
+            outputs: Mutex::new(Vec::new()),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Pop an orphaned output, registering the waker from
+    /// `cx` to be woken when the next one arrives.
+    pub fn poll_pop(&self, cx: &mut Context) -> Option<IndexedOutput<T>> {
+        self.waker.register(cx.waker());
+        self.outputs.lock().unwrap().pop()
+    }
+
+    /// Number of orphaned outputs yet to be popped.
+    pub fn len(&self) -> usize {
+        self.outputs.lock().unwrap().len()
+    }
+
+    fn push(&self, output: IndexedOutput<T>) {
+        self.outputs.lock().unwrap().push(output);
+        self.waker.wake();
+    }
+}
+
+struct Slot<T> {
+    handle_alive: bool,
+    finished: bool,
+    aborted: bool,
+    failed: bool,
+    // Whether the output was deemed a failure by `failure`;
+    // see `ScopeHandle::finished_or_failed_with`.
+    errored: bool,
+    failure: Option<fn(&T) -> bool>,
+    skipped: bool,
+    output: Option<Output<T>>,
+}
+
+/// State shared between a spawned task and its handle.
+pub(crate) struct Shared<T> {
+    slot: Mutex<Slot<T>>,
+    waker: AtomicWaker,
+    abort: AbortHandle,
+    orphans: Arc<Orphans<T>>,
+    index: usize,
+    // Woken once the task is done; see `Finished`.
+    watchers: Mutex<Vec<Waker>>,
+}
+
+impl<T> Shared<T> {
+    fn abandon(&self) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.finished = true;
+        slot.aborted = true;
+        slot.failed = true;
+        std::mem::drop(slot);
+        self.waker.wake();
+    }
+}
+
+/// Guard waking the watchers of a task once it is done, or
+/// dropped midway (say, as it panics, if panics are not
+/// caught), in which case it is marked as failed.
+struct Notify<T>(Arc<Shared<T>>);
+
+impl<T> Drop for Notify<T> {
+    fn drop(&mut self) {
+        let mut slot = self.0.slot.lock().unwrap();
+        if !slot.finished {
+            slot.finished = true;
+            slot.failed = true;
+        }
+        std::mem::drop(slot);
+        for waker in std::mem::take(&mut *self.0.watchers.lock().unwrap()) {
+            waker.wake();
+        }
+    }
+}
+
+/// A future resolving once a spawned future is done, to
+/// whether it failed: panicked, or was aborted (or, if
+/// set, yielded an output deemed a failure). Created by
+/// `ScopeHandle::finished`.
+pub(crate) struct Finished<T>(Arc<Shared<T>>, bool);
+
+impl<T> Future for Finished<T> {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<bool> {
+        let mut watchers = self.0.watchers.lock().unwrap();
+        let slot = self.0.slot.lock().unwrap();
+        if slot.finished {
+            return Poll::Ready(slot.failed || (self.1 && slot.errored));
+        }
+        if !watchers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            watchers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// The result of a spawned task, as received by the
+/// `Scope` stream.
+pub(crate) enum Completion<T> {
+    /// The output is to be yielded by the stream.
+    Output(IndexedOutput<T>),
+    /// The output was sent to the handle.
+    Routed(Arc<Shared<T>>),
+    /// The task (of the given index) was aborted.
+    Aborted(usize),
+}
+
+impl<T> Completion<T> {
+    /// The output to be yielded by the stream, if any. If
+    /// the output was sent to a handle that has since been
+    /// dropped, the output is reclaimed from the handle. If
+    /// the task was aborted, its index is returned as an
+    /// `Err`.
+    pub fn into_output(self) -> Option<Result<IndexedOutput<T>, usize>> {
+        match self {
+            Completion::Output(output) => Some(Ok(output)),
+            Completion::Routed(shared) => {
+                let mut slot = shared.slot.lock().unwrap();
+                if slot.handle_alive {
+                    slot.skipped = true;
+                    None
+                } else {
+                    slot.output.take().map(|output| Ok((shared.index, output)))
+                }
+            },
+            Completion::Aborted(index) => Some(Err(index)),
+        }
+    }
+}
+
+/// Wrap `fut` to report its output to a `ScopeHandle`. The
+/// output is sent to the handle if it is alive when `fut`
+/// completes. If `catch_panics` is set, a panic while
+/// polling `fut` is caught and reported as its output,
+/// tagged with `index`. If `abortable` is not set,
+/// `ScopeHandle::abort` has no effect. `finish` is called
+/// once `fut` completes (or panics, with the caught panic),
+/// unless it is aborted.
+pub(crate) fn wrap<'a, T, F, Fi>(fut: F, orphans: Arc<Orphans<T>>,
+                                 index: usize, catch_panics: bool,
+                                 abortable: bool, finish: Fi)
+                                 -> (impl Future<Output=Completion<T>> + Send + 'a,
+                                     ScopeHandle<'a, T>)
+where T: Send + 'static, F: Future<Output=T> + Send + 'a,
+      Fi: FnOnce(Option<&CaughtPanic>) + Send + 'a
+{
+    let (abort, registration) = AbortHandle::new_pair();
+    let shared = Arc::new(Shared {
+        slot: Mutex::new(Slot {
+            handle_alive: true,
+            finished: false,
+            aborted: false,
+            failed: false,
+            errored: false,
+            failure: None,
+            skipped: false,
+            output: None,
+        }),
+        waker: AtomicWaker::new(),
+        abort,
+        orphans,
+        index,
+        watchers: Mutex::new(Vec::new()),
+    });
+
+    let task_shared = shared.clone();
+    let task = async move {
+        let _notify = Notify(task_shared.clone());
+        let fut = async move {
+            if abortable {
+                WithAbort::new(fut, registration).await
+            } else {
+                Ok(fut.await)
+            }
+        };
+        let result = if catch_panics {
+            match AssertUnwindSafe(fut).catch_unwind().await {
+                Ok(result) => result.map(Ok),
+                Err(payload) => Ok(Err(CaughtPanic::new(payload, Some(index)))),
+            }
+        } else {
+            fut.await.map(Ok)
+        };
+
+        match result {
+            Ok(output) => {
+                finish(output.as_ref().err());
+                let mut slot = task_shared.slot.lock().unwrap();
+                slot.finished = true;
+                slot.failed = output.is_err();
+                slot.errored = match (&output, slot.failure) {
+                    (Ok(output), Some(failure)) => failure(output),
+                    _ => false,
+                };
+                if slot.handle_alive {
+                    slot.output = Some(output);
+                    std::mem::drop(slot);
+                    task_shared.waker.wake();
+                    Completion::Routed(task_shared)
+                } else {
+                    Completion::Output((index, output))
+                }
+            },
+            Err(_) => {
+                task_shared.abandon();
+                Completion::Aborted(index)
+            }
+        }
+    };
+
+    (task, ScopeHandle { shared, _marker: PhantomData })
+}
+
+/// Similar to `Abortable`, but the future is never borrowed
+/// as a whole: `Abortable` checks whether it is aborted via
+/// a shared reference to itself, which Miri (under Stacked
+/// Borrows) reports as invalidating the self-references of
+/// the future. Here, only a pending future is wrapped in an
+/// `Abortable`, to wait on the registration.
+#[pin_project]
+struct WithAbort<F> {
+    #[pin]
+    fut: F,
+    abort: Abortable<Pending<()>>,
+}
+
+impl<F> WithAbort<F> {
+    fn new(fut: F, registration: AbortRegistration) -> Self {
+        WithAbort { fut, abort: Abortable::new(futures::future::pending(), registration) }
+    }
+}
+
+impl<F: Future> Future for WithAbort<F> {
+    type Output = Result<F::Output, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(Err(aborted)) = this.abort.poll_unpin(cx) {
+            return Poll::Ready(Err(aborted));
+        }
+        this.fut.poll(cx).map(Ok)
+    }
+}
+
+/// A handle to a future spawned in a `Scope`. Awaiting the
+/// handle yields the output of the future, and the output
+/// is then not yielded by the `Scope` stream. If the handle
+/// is dropped before it yields the output, the output is
+/// yielded by the stream as usual (though possibly out of
+/// order if the stream has already reached the task).
+///
+/// The handle carries the lifetime `'a` of the scope, and
+/// hence can not be used once the data borrowed by the
+/// scope is no longer alive.
+///
+/// # Panics
+///
+/// Awaiting the handle of an aborted future panics. If the
+/// future panicked (and the panic was caught by the scope),
+/// the panic is resumed when awaiting the handle.
+pub struct ScopeHandle<'a, T> {
+    shared: Arc<Shared<T>>,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>
+}
+
+impl<'a, T> ScopeHandle<'a, T> {
+    /// Abort the future. The future is dropped the next
+    /// time the executor polls it, and its output is not
+    /// yielded by the `Scope` stream. This has no effect
+    /// if the future has already finished.
+    pub fn abort(&self) {
+        self.shared.abort.abort();
+    }
+
+    /// The handle aborting the future, as in `abort`.
+    pub(crate) fn abort_handle(&self) -> AbortHandle {
+        self.shared.abort.clone()
+    }
+
+    /// Take the output of the finished future, unless it
+    /// panicked (the panic is then left to be yielded).
+    pub(crate) fn take_output(&self) -> Option<T> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        match slot.output.take() {
+            Some(Ok(output)) => Some(output),
+            output => {
+                slot.output = output;
+                None
+            }
+        }
+    }
+
+    /// A future resolving once the future is done, to
+    /// whether it failed; see `Finished`.
+    pub(crate) fn finished(&self) -> Finished<T> {
+        Finished(self.shared.clone(), false)
+    }
+
+    /// Similar to `finished`, but an output for which
+    /// `failure` holds also counts as a failure. If the
+    /// future has finished, its output is still held by the
+    /// (alive) handle, and is checked at once.
+    pub(crate) fn finished_or_failed_with(&self, failure: fn(&T) -> bool) -> Finished<T> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        if slot.finished {
+            if let Some(Ok(output)) = &slot.output {
+                slot.errored = failure(output);
+            }
+        } else {
+            slot.failure = Some(failure);
+        }
+        std::mem::drop(slot);
+        Finished(self.shared.clone(), true)
+    }
+
+    /// Whether the future has finished, either by
+    /// completing or by being aborted.
+    pub fn is_finished(&self) -> bool {
+        self.shared.slot.lock().unwrap().finished
+    }
+}
+
+impl<'a, T> Future for ScopeHandle<'a, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context)
+            -> Poll<Self::Output> {
+        self.shared.waker.register(cx.waker());
+        let mut slot = self.shared.slot.lock().unwrap();
+        if let Some(output) = slot.output.take() {
+            std::mem::drop(slot);
+            match output {
+                Ok(output) => Poll::Ready(output),
+                Err(caught) => std::panic::resume_unwind(caught.into_payload()),
+            }
+        } else if slot.aborted {
+            panic!("awaited the handle of an aborted future");
+        } else if slot.finished {
+            panic!("handle polled after completion");
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, T> Drop for ScopeHandle<'a, T> {
+    fn drop(&mut self) {
+        let mut slot = self.shared.slot.lock().unwrap();
+        slot.handle_alive = false;
+        if slot.skipped {
+            if let Some(output) = slot.output.take() {
+                std::mem::drop(slot);
+                self.shared.orphans.push((self.shared.index, output));
+            }
+        }
+    }
+}
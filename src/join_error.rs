@@ -0,0 +1,56 @@
+use std::fmt;
+use crate::CaughtPanic;
+
+/// The error yielded for a spawned future that did not
+/// complete, by a scope created via `Scope::with_join_errors`.
+#[derive(Debug)]
+pub enum JoinError {
+    /// The future panicked.
+    Panic(CaughtPanic),
+
+    /// The future was aborted via its `ScopeHandle`, and
+    /// hence did not produce an output. Note that a future
+    /// spawned via `spawn_cancellable` yields its default
+    /// value when cancelled, and not this error.
+    Cancelled {
+        /// Index (in order of spawning) of the future.
+        index: usize,
+    },
+}
+
+impl JoinError {
+    /// Index (in order of spawning) of the future, if known.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            JoinError::Panic(caught) => caught.index(),
+            JoinError::Cancelled { index } => Some(*index),
+        }
+    }
+
+    /// Whether the future panicked.
+    #[inline]
+    pub fn is_panic(&self) -> bool { matches!(self, JoinError::Panic(_)) }
+
+    /// Whether the future was aborted.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool { matches!(self, JoinError::Cancelled { .. }) }
+
+    /// The caught panic, if the future panicked.
+    pub fn try_into_panic(self) -> Result<CaughtPanic, Self> {
+        match self {
+            JoinError::Panic(caught) => Ok(caught),
+            err => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Panic(caught) => caught.fmt(f),
+            JoinError::Cancelled { index } => write!(f, "spawned future #{} was aborted", index),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
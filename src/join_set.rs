@@ -0,0 +1,77 @@
+use std::future::Future;
+
+use futures::future::AbortHandle;
+
+use crate::{Scope, Joined, JoinError, TokioSpawner};
+
+/// A wrapper of a `Scope` with the interface of
+/// `tokio::task::JoinSet`, to ease migrating from it: the
+/// methods below have the signatures of their `JoinSet`
+/// counterparts, except that the futures may borrow data of
+/// lifetime 'a.
+///
+/// The semantics differ where the lifetime requires it:
+///
+/// - Creating the set is unsafe, as for `Scope::create`;
+///   likewise, dropping it blocks the current thread until
+///   the tasks are cancelled (rather than merely aborting
+///   them), and it must not be forgotten.
+/// - `spawn` returns a `futures::future::AbortHandle` rather
+///   than a `tokio::task::AbortHandle`, and the errors are
+///   this crate's `JoinError`, which has the accessors of
+///   tokio's (`is_panic`, `is_cancelled`, `try_into_panic`).
+pub struct ScopedJoinSet<'a, T> {
+    scope: Joined<'a, T, TokioSpawner>,
+}
+
+impl<'a, T: Send + 'static> ScopedJoinSet<'a, T> {
+    /// Create an empty set, spawning onto the ambient tokio
+    /// runtime.
+    ///
+    /// # Safety
+    ///
+    /// The same considerations as `Scope::create` apply.
+    pub unsafe fn new() -> Self {
+        ScopedJoinSet { scope: Scope::create_with(TokioSpawner::default()).with_join_errors() }
+    }
+
+    /// Spawn `task` onto the set; its output is yielded by
+    /// `join_next`. The returned handle aborts the task,
+    /// which is then yielded as a cancelled `JoinError`.
+    pub fn spawn<F>(&mut self, task: F) -> AbortHandle
+    where F: Future<Output=T> + Send + 'a
+    {
+        self.scope.spawn(task).abort_handle()
+    }
+
+    /// Wait for one of the tasks to complete, returning its
+    /// output, or `None` if the set is empty.
+    pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        self.scope.join_next().await
+    }
+
+    /// Abort all the tasks of the set; they are still
+    /// yielded by `join_next`, as cancelled `JoinError`s if
+    /// they did not complete first.
+    pub fn abort_all(&mut self) {
+        self.scope.abort_all();
+        self.scope.abort_pending();
+    }
+
+    /// Abort all the tasks, and wait for them to finish.
+    pub async fn shutdown(&mut self) {
+        self.abort_all();
+        while self.join_next().await.is_some() {}
+    }
+
+    /// Number of tasks in the set, that are yet to be
+    /// yielded by `join_next`.
+    pub fn len(&self) -> usize {
+        self.scope.remaining()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.scope.is_empty()
+    }
+}
@@ -1,7 +1,8 @@
 //! Enables controlled spawning of non-`'static` futures
-//! when using the [async-std][async_std] executor. Note
-//! that this idea is similar to `crossbeam::scope`, and
-//! `rayon::scope` but asynchronous.
+//! when using the [async-std][async_std] or
+//! [tokio][tokio] executors. Note that this idea is similar
+//! to `crossbeam::scope`, and `rayon::scope` but
+//! asynchronous.
 //!
 //! ## Motivation
 //!
@@ -34,24 +35,31 @@
 //! [`scope_and_block`][scope_and_block], used as follows:
 //!
 //! ``` rust
-//! #[async_std::test]
-//! async fn scoped_futures() {
-//!     let not_copy = String::from("hello world!");
-//!     let not_copy_ref = &not_copy;
-//!     let (foo, outputs) = async_scoped::scope_and_block(|s| {
-//!         for _ in 0..10 {
-//!             let proc = || async {
-//!                 assert_eq!(not_copy_ref, "hello world!");
-//!             };
-//!             s.spawn(proc());
-//!         }
-//!         42
-//!     });
-//!     assert_eq!(foo, 42);
-//!     assert_eq!(outputs.len(), 10);
-//! }
+//! # #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+//! # {
+//! # #[cfg(feature = "tokio")]
+//! # let runtime = tokio::runtime::Runtime::new().unwrap();
+//! # #[cfg(feature = "tokio")]
+//! # let _guard = runtime.enter();
+//! let not_copy = String::from("hello world!");
+//! let not_copy_ref = &not_copy;
+//! let (foo, outputs) = async_scoped::scope_and_block(|s| {
+//!     for _ in 0..10 {
+//!         let proc = || async {
+//!             assert_eq!(not_copy_ref, "hello world!");
+//!         };
+//!         s.spawn(proc());
+//!     }
+//!     42
+//! });
+//! assert_eq!(foo, 42);
+//! assert_eq!(outputs.len(), 10);
+//! # }
 //! ```
 //!
+//! Under `tokio`, the current thread must be within (or
+//! enter) a multi-threaded runtime, as above.
+//!
 //! The [`scope_and_block`][scope_and_block] function above
 //! blocks the current thread until all spawned futures are
 //! driven in order to guarantee safety.
@@ -61,6 +69,62 @@
 //! asynchronous, and does not block the current thread.
 //! However, the user should ensure that the returned future
 //! _is not forgetten_ before being driven to completion.
+//! The outputs are collected in the order of completion;
+//! use [`scope_and_collect_ordered`][scope_and_collect_ordered]
+//! to collect them in the order of spawning. Futures that
+//! are not `Send` may be spawned on the current thread in
+//! a [`LocalScope`][LocalScope], say via
+//! [`local_scope_and_collect`][local_scope_and_collect].
+//!
+//! The value returned by the block (the closure that spawns
+//! the futures) is passed through by every function: the
+//! `scope_and_*` functions return it first, followed by the
+//! outputs of the futures, say `(R, Vec<T>)`. Only
+//! [`scope`][scope] returns the stream first, as
+//! `(Scope, R)`; this order is kept for compatibility.
+//!
+//! ## Executors
+//!
+//! A `Scope` drives its futures using a
+//! [`Spawner`][Spawner]. The `async-std` feature (enabled
+//! by default) provides [`AsyncStdSpawner`][AsyncStdSpawner]
+//! which spawns using `async_std::task::spawn`, while the
+//! `tokio` feature provides `TokioSpawner` which spawns
+//! using `tokio::task::spawn`, and the `smol` feature
+//! provides `SmolSpawner` which spawns using `smol::spawn`.
+//! [`scope`][scope] and the related functions use the
+//! [`DefaultSpawner`][DefaultSpawner]: `TokioSpawner` if
+//! the `tokio` feature is enabled, else `SmolSpawner` if
+//! the `smol` feature is enabled, and `AsyncStdSpawner`
+//! otherwise. The API is identical in all cases, except
+//! that smol does not support `LocalScope`. Without any of
+//! these features, there is no `DefaultSpawner`: scopes are
+//! then created via `Scope::create_with` (or
+//! `scope_and_block_on`), given a `Spawner` for the
+//! executor at hand.
+//!
+//! To spawn onto a specific tokio runtime, say from a
+//! thread outside of it, create the scope via
+//! `Scope::create_with_handle`. To abort the cancellable
+//! futures once a `tokio_util::sync::CancellationToken` is
+//! cancelled, create it via `Scope::create_with_token`.
+//!
+//! For synchronous code, the `threads` feature provides
+//! `thread_scope`, to spawn non 'static closures onto OS
+//! threads (without a runtime) and collect their outputs.
+//!
+//! Other executors may be supported by implementing the
+//! `Spawner` trait, and creating the scope via
+//! [`Scope::create_with`][Scope::create_with].
+//!
+//! Under `tokio`, the futures must be spawned from within a
+//! multi-threaded runtime: blocking on the scope (say, via
+//! [`scope_and_block`][scope_and_block] or by dropping it)
+//! uses `tokio::task::block_in_place`, which is not
+//! supported by the current-thread runtime. To block within
+//! a single-threaded executor, use
+//! [`scope_and_block_on`][scope_and_block_on] with a
+//! spawner that drives the futures on other threads.
 //!
 //! ## Cancellation
 //!
@@ -71,13 +135,39 @@
 //! cancellable futures are scheduled for cancellation. In
 //! the next poll of the futures, they are dropped and a
 //! default value (provided by a closure during spawn) is
-//! returned as the output of the future.
+//! returned as the output of the future. Use
+//! [`Scope::with_drop_policy`][Scope::with_drop_policy] to
+//! instead wait for all the futures when dropping a scope.
 //!
 //! **Note:** this is an abrupt, hard cancellation. It also
 //! requires a reasonable behaviour: futures that do not
 //! return control to the executor cannot be cancelled once
 //! it has started.
 //!
+//! For a deterministic teardown, use
+//! [`Scope::graceful_shutdown`][Scope::graceful_shutdown]:
+//! the futures are given a deadline to complete, after
+//! which the cancellable futures are cancelled.
+//!
+//! ## Panics
+//!
+//! Panics in the spawned futures are caught by the scope,
+//! and resumed when the stream yields the output of the
+//! panicking future. Use [`Scope::caught`][Scope::caught] or
+//! [`scope_and_collect_caught`][scope_and_collect_caught] to
+//! observe them as [`CaughtPanic`][CaughtPanic] errors
+//! instead. This may be disabled via
+//! [`Scope::catch_panics`][Scope::catch_panics].
+//!
+//! ## Tracing
+//!
+//! Under the `tracing` feature, each spawned future is
+//! instrumented with a `scope.task` span carrying the
+//! `scope.task_id` field (the index of the future, in order
+//! of spawning). The span is a child of the span active
+//! when the future is spawned, and is entered only while
+//! the future is being polled.
+//!
 //! ## Safety Considerations
 //!
 //! The [`scope`][scope] API provided in this crate is
@@ -117,22 +207,125 @@
 //! API here is _inherently unsafe_.
 //!
 //! [async-std]: async_std
+//! [tokio]: https://docs.rs/tokio
 //! [poll]: std::futures::Future::poll
 //! [Task]: std::task::Task
 //! [forget]: std::mem::forget
 //! [Stream]: futures::Stream
 //! [for_each_concurrent]: futures::StreamExt::for_each_concurrent
+#[macro_use]
+mod macros;
+
 mod cancellable_future;
-pub(crate) use cancellable_future::CancellableFuture;
+pub(crate) use cancellable_future::{CancellableFuture, WithCancel};
 
 mod scoped;
-pub use scoped::Scope;
+pub use scoped::{Scope, Caught, Indexed, Joined, Tagged, Timed, ScopeStream};
+
+mod builder;
+pub use builder::{ScopeBuilder, BuildError};
+
+// Local futures are not supported by smol.
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+mod local;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub use local::LocalScope;
+
+mod panic;
+pub use panic::CaughtPanic;
+
+mod join_error;
+pub use join_error::JoinError;
+
+mod handle;
+pub use handle::ScopeHandle;
+
+mod context;
+pub use context::current_context;
+
+mod abort_handle;
+pub use abort_handle::ScopeAbortHandle;
+
+mod outcome;
+pub use outcome::TaskOutcome;
+
+mod timeout;
+pub use timeout::Elapsed;
+
+mod drop_policy;
+pub use drop_policy::DropPolicy;
+
+mod fairness;
+pub use fairness::Fairness;
+
+mod backpressure;
+pub use backpressure::BackpressureStats;
+
+mod timing;
+pub use timing::TimingStart;
+
+#[cfg(feature = "tokio")]
+mod join_set;
+#[cfg(feature = "tokio")]
+pub use join_set::ScopedJoinSet;
+
+mod shutdown;
+pub use shutdown::{ShutdownSummary, PartialOutputs};
+
+mod progress;
+pub use progress::{ProgressSender, ProgressEvent, ProgressEvents};
+
+mod log;
+pub use log::{Logger, LogLine};
+
+mod spawn_handle;
+pub use spawn_handle::{SpawnHandle, DepthExceeded};
+
+mod tasks;
+mod limit;
+pub use limit::Permits;
+mod memory;
+mod rate;
+mod ready;
+mod state;
+mod tag;
+
+#[cfg(feature = "threads")]
+mod thread;
+#[cfg(feature = "threads")]
+pub use thread::{thread_scope, ThreadScope};
 
 mod usage;
-pub use usage::{scope, scope_and_block, scope_and_collect};
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub use usage::{scope, scope_into_results, scope_and_block, scope_and_block_caught, scope_and_block_with, scope_and_collect, scope_and_collect_into,
+                scope_and_collect_ordered, scope_join_all, scope_and_collect_caught, scope_and_process,
+                scope_and_collect_n, scope_and_collect_until,
+                scope_and_collect_timeout, select_scope,
+                try_scope_and_collect, scope_and_collect_results,
+                scope_and_collect_map, scope_and_collect_grouped, scope_and_collect_logged,
+                scope_for_each_concurrent, scope_buffer_unordered};
+pub use usage::scope_and_block_on;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub use usage::local_scope_and_collect;
 
 mod cancellation;
 pub(crate) use cancellation::Cancellation;
+pub use cancellation::CancelReason;
+
+mod spawner;
+pub use spawner::{Spawner, LocalSpawner, ExecutorRef};
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub use spawner::DefaultSpawner;
+#[cfg(feature = "test-util")]
+pub use spawner::InlineSpawner;
+#[cfg(feature = "async-std")]
+pub use spawner::AsyncStdSpawner;
+#[cfg(feature = "tokio")]
+pub use spawner::{TokioSpawner, TokioHandle};
+#[cfg(feature = "smol")]
+pub use spawner::SmolSpawner;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub use spawner::DefaultLocalSpawner;
 
-#[cfg(test)]
+#[cfg(all(test, any(feature = "async-std", feature = "tokio", feature = "smol")))]
 mod tests;
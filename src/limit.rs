@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Poll, Context, Waker};
+
+/// Tracks the number (and total weight) of spawned futures
+/// that are still being driven by the executor, to bound
+/// the concurrency of a `Scope`. While paused, no futures
+/// may be driven. The weight reserved via `Scope::reserve`
+/// counts towards the limit until spawned (or released).
+/// Every waiter (say, several producers sharing the scope)
+/// is woken as the limit frees up, or the scope resumes.
+pub(crate) struct Limiter {
+    in_flight: AtomicUsize,
+    weight: AtomicUsize,
+    reserved: AtomicUsize,
+    paused: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Limiter {
+            in_flight: AtomicUsize::new(0),
+            weight: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Number of futures still being driven.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Total weight of the futures still being driven.
+    pub fn weight(&self) -> usize {
+        self.weight.load(Ordering::SeqCst)
+    }
+
+    /// Weight reserved via `poll_reserve`, yet to be
+    /// spawned.
+    pub fn reserved(&self) -> usize {
+        self.reserved.load(Ordering::SeqCst)
+    }
+
+    /// Whether the limiter is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.wake_all();
+    }
+
+    /// Register the waker of `cx` to be woken by `wake_all`.
+    fn register(&self, cx: &mut Context) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+    }
+
+    /// Wake all the waiters, which check whether they may
+    /// proceed as they are polled again.
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Register a future of the given weight as being
+    /// driven. The returned guard should be dropped once
+    /// the future completes (or is dropped).
+    pub fn enter(self: &Arc<Self>, weight: usize) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.weight.fetch_add(weight, Ordering::SeqCst);
+        InFlight { limiter: self.clone(), weight }
+    }
+
+    /// Ready once a future of the given weight may be
+    /// driven without the total weight exceeding `limit`
+    /// (if any), or if no futures are being driven; never
+    /// ready while paused.
+    pub fn poll_below(&self, limit: Option<usize>, weight: usize, cx: &mut Context) -> Poll<()> {
+        self.register(cx);
+        if self.is_below(limit, weight) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Whether a future of the given weight may be driven
+    /// now; see `poll_below`.
+    pub fn is_below(&self, limit: Option<usize>, weight: usize) -> bool {
+        !self.is_paused() && fits(limit, self.weight() + self.reserved(), weight)
+    }
+
+    /// Reserve `n` of the limit, once it fits as in
+    /// `poll_below`. The reservation should be released via
+    /// `unreserve`, as it is spawned (or dropped).
+    pub fn poll_reserve(&self, limit: Option<usize>, n: usize, cx: &mut Context) -> Poll<()> {
+        self.register(cx);
+        loop {
+            let reserved = self.reserved();
+            if self.is_paused() || !fits(limit, self.weight() + reserved, n) {
+                return Poll::Pending;
+            }
+            if self.reserved.compare_exchange(reserved, reserved + n,
+                                              Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                return Poll::Ready(());
+            }
+        }
+    }
+
+    /// Release `n` of the reserved weight.
+    pub fn unreserve(&self, n: usize) {
+        if n > 0 {
+            self.reserved.fetch_sub(n, Ordering::SeqCst);
+            self.wake_all();
+        }
+    }
+}
+
+/// Whether `weight` more fits in `limit` given the `current`
+/// weight: always, if nothing is being driven.
+fn fits(limit: Option<usize>, current: usize, weight: usize) -> bool {
+    match limit {
+        Some(limit) => current == 0 || current + weight <= limit,
+        None => true,
+    }
+}
+
+/// Permits reserved from the concurrency limit of a `Scope`,
+/// via `Scope::reserve`. Each future spawned via
+/// `Scope::spawn_reserved` consumes one permit; dropping the
+/// permits releases the unused ones.
+pub struct Permits {
+    limiter: Arc<Limiter>,
+    remaining: AtomicUsize,
+}
+
+impl Permits {
+    pub(crate) fn new(limiter: Arc<Limiter>, n: usize) -> Self {
+        Permits { limiter, remaining: AtomicUsize::new(n) }
+    }
+
+    /// Number of permits yet to be consumed.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// Whether the permits were reserved from `limiter`.
+    pub(crate) fn is_from(&self, limiter: &Arc<Limiter>) -> bool {
+        Arc::ptr_eq(&self.limiter, limiter)
+    }
+
+    /// Consume a permit, if any is left. The permit should
+    /// be released via `Limiter::unreserve` once its future
+    /// has entered the limiter.
+    pub(crate) fn take(&self) -> bool {
+        self.remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst,
+                                    |remaining| remaining.checked_sub(1)).is_ok()
+    }
+}
+
+impl Drop for Permits {
+    fn drop(&mut self) {
+        self.limiter.unreserve(*self.remaining.get_mut());
+    }
+}
+
+/// Guard marking a future as being driven by the executor.
+pub(crate) struct InFlight {
+    limiter: Arc<Limiter>,
+    weight: usize,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.limiter.weight.fetch_sub(self.weight, Ordering::SeqCst);
+        self.limiter.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.limiter.wake_all();
+    }
+}
@@ -0,0 +1,177 @@
+use std::task::{Poll, Context};
+use std::pin::Pin;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use futures::{Stream, Future, FutureExt};
+use futures::future::LocalBoxFuture;
+use futures::stream::{FuturesUnordered, FusedStream};
+
+use pin_project::{pin_project, pinned_drop};
+use crate::{Cancellation, CancelReason, LocalSpawner};
+
+/// A scope to spawn non 'static futures that need not be
+/// `Send`, say as they hold an `Rc`. The futures are
+/// spawned on the executor of the current thread, via
+/// `LocalSpawner::spawn_local`, and hence the scope must be
+/// created (and driven) on a thread running such an
+/// executor. Under `tokio`, this is a `LocalSet`.
+///
+/// Similar to `Scope`, this is a `Stream` of the outputs of
+/// the spawned futures, and dropping it blocks the current
+/// thread until the futures complete.
+///
+/// # Safety
+///
+/// This type uses `Drop` implementation to guarantee
+/// safety. It is not safe to forget this object unless it
+/// is driven to completion. Under `tokio`, the futures can
+/// not progress while the thread is blocked, and hence the
+/// scope must be driven to completion before being dropped.
+#[pin_project(PinnedDrop)]
+pub struct LocalScope<'a, T, Sp: LocalSpawner = crate::DefaultLocalSpawner> {
+    done: bool,
+    len: usize,
+    remaining: usize,
+    cancellation: Arc<Cancellation>,
+    spawner: Sp,
+    #[pin]
+    futs: FuturesUnordered<Sp::Handle<T>>,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>
+}
+
+impl<'a, T: 'static> LocalScope<'a, T> {
+    /// Create a LocalScope object using the
+    /// `DefaultLocalSpawner`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create() -> Self {
+        LocalScope::create_with(crate::DefaultLocalSpawner::default())
+    }
+}
+
+impl<'a, T: 'static, Sp: LocalSpawner> LocalScope<'a, T, Sp> {
+    /// Create a LocalScope object that spawns futures using
+    /// `spawner`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create_with(spawner: Sp) -> Self {
+        LocalScope {
+            done: false,
+            len: 0,
+            remaining: 0,
+            cancellation: Arc::new(Cancellation::new()),
+            spawner,
+            futs: FuturesUnordered::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Spawn a future with the executor's `spawn_local`.
+    /// The future is expected to be driven to completion
+    /// before 'a expires.
+    pub fn spawn_local<F: Future<Output=T> + 'a>(&mut self, f: F) {
+        let handle = self.spawner.spawn_local(unsafe {
+            std::mem::transmute::<LocalBoxFuture<'a, T>,
+                                  LocalBoxFuture<'static, T>>(f.boxed_local())
+        });
+        self.futs.push(handle);
+        self.done = false;
+        self.len += 1;
+        self.remaining += 1;
+    }
+
+    /// Spawn a cancellable future with the executor's
+    /// `spawn_local`; see `Scope::spawn_cancellable`.
+    #[inline]
+    pub fn spawn_local_cancellable<F: Future<Output=T> + 'a,
+                                   Fu: FnOnce() -> T + 'a>(
+        &mut self, f: F, default: Fu
+    ) {
+        self.spawn_local(crate::CancellableFuture::new(
+            self.cancellation.clone(), f, move |_| default()
+        ))
+    }
+}
+
+impl<'a, T, Sp: LocalSpawner> LocalScope<'a, T, Sp> {
+    /// Cancel all futures spawned with cancellation.
+    #[inline]
+    pub async fn cancel(&self) {
+        self.cancellation.cancel(CancelReason::AbortAll).await;
+    }
+
+    /// Total number of futures spawned in this scope.
+    #[inline]
+    pub fn len(&self) -> usize { self.len }
+
+    /// Number of futures spawned in this scope, whose
+    /// output has not yet been yielded by the stream.
+    #[inline]
+    pub fn remaining(&self) -> usize { self.remaining }
+
+    /// Whether no futures remain in this scope.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.remaining == 0 }
+
+    /// Collect the outputs of the futures into a `Vec`.
+    pub async fn collect(&mut self) -> Vec<T> {
+        let mut proc_outputs = Vec::with_capacity(self.remaining);
+        while let Some(item) = self.join_next().await {
+            proc_outputs.push(item);
+        }
+        proc_outputs
+    }
+
+    /// The next output of the stream, or `None` once all
+    /// the spawned futures are done.
+    pub async fn join_next(&mut self) -> Option<T> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<'a, T, Sp: LocalSpawner> Stream for LocalScope<'a, T, Sp> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let poll = this.futs.poll_next(cx);
+        match poll {
+            Poll::Ready(Some(_)) => *this.remaining -= 1,
+            Poll::Ready(None) => *this.done = true,
+            Poll::Pending => {},
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, Sp: LocalSpawner> FusedStream for LocalScope<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[pinned_drop]
+impl<'a, T, Sp: LocalSpawner> PinnedDrop for LocalScope<'a, T, Sp> {
+    fn drop(mut self: Pin<&mut Self>) {
+        if !self.done {
+            Sp::block_on(async {
+                self.cancellation.cancel(CancelReason::ScopeDropped).await;
+                self.collect().await;
+            });
+        }
+    }
+}
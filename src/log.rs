@@ -0,0 +1,43 @@
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// A line logged by a future spawned via
+/// `Scope::spawn_logged`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// The index (in order of spawning) of the future.
+    pub index: usize,
+
+    /// The message logged.
+    pub message: String,
+}
+
+/// The log buffer of a scope, shared by its `Logger`s.
+pub(crate) type LogBuffer = Arc<Mutex<Vec<LogLine>>>;
+
+/// Logs lines to the buffer of a scope, from a future
+/// spawned via `Scope::spawn_logged`; the lines are taken
+/// via `Scope::take_logs`. The logger carries the lifetime
+/// `'a` of the scope, and hence can not outlive it.
+#[derive(Clone)]
+pub struct Logger<'a> {
+    buffer: LogBuffer,
+    index: usize,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>
+}
+
+impl<'a> Logger<'a> {
+    pub(crate) fn new(buffer: LogBuffer, index: usize) -> Self {
+        Logger { buffer, index, _marker: PhantomData }
+    }
+
+    /// Append `message` to the log of the scope, tagged
+    /// with the index of the future. The lines of all the
+    /// futures are kept in the order they are logged.
+    pub fn log(&self, message: impl Into<String>) {
+        let line = LogLine { index: self.index, message: message.into() };
+        self.buffer.lock().unwrap().push(line);
+    }
+}
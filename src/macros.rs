@@ -0,0 +1,44 @@
+/// Spawn non 'static futures in a scope, and collect their
+/// outputs, without `unsafe` at the call-site. The macro
+/// accepts a closure taking the `&mut Scope`, and expands
+/// to a call to [`scope_and_block`][crate::scope_and_block].
+/// It evaluates to the `Vec` of the outputs of the spawned
+/// futures.
+///
+/// # Panics / blocking
+///
+/// The macro does not yield a future: the current thread
+/// is blocked until the spawned futures complete, which is
+/// what makes it safe. Within async code, it stalls the
+/// executor thread it runs on for as long as the futures
+/// take; prefer `scope_and_collect` there. The same
+/// considerations as `scope_and_block` apply: the panic of
+/// a spawned future is resumed once the others complete,
+/// and under `tokio`, it panics within the current-thread
+/// runtime.
+///
+/// ``` rust
+/// # #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+/// # {
+/// # #[cfg(feature = "tokio")]
+/// # let runtime = tokio::runtime::Runtime::new().unwrap();
+/// # #[cfg(feature = "tokio")]
+/// # let _guard = runtime.enter();
+/// let not_copy = String::from("hello world!");
+/// let not_copy_ref = &not_copy;
+/// let outputs = async_scoped::scoped_blocking!(|s| {
+///     for _ in 0..10 {
+///         s.spawn(async move { not_copy_ref.len() });
+///     }
+/// });
+/// assert_eq!(outputs, vec![12; 10]);
+/// # }
+/// ```
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+#[macro_export]
+macro_rules! scoped_blocking {
+    (|$s:ident| $body:expr) => {{
+        let (_, outputs) = $crate::scope_and_block(|$s| $body);
+        outputs
+    }};
+}
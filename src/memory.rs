@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use crate::limit::Limiter;
+
+/// Interval between readings of the resident set size,
+/// while waiting for it to drop.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The resident set size of the process in bytes, if it
+/// may be read: requires the `memory-stats` feature.
+#[cfg(feature = "memory-stats")]
+pub(crate) fn resident_bytes() -> Option<usize> {
+    memory_stats::memory_stats().map(|stats| stats.physical_mem)
+}
+
+#[cfg(not(feature = "memory-stats"))]
+pub(crate) fn resident_bytes() -> Option<usize> {
+    None
+}
+
+/// Whether another future may be driven given the memory
+/// `limit`: the resident set size is within it, or is not
+/// known, or no futures are being driven.
+pub(crate) fn is_below(limit: usize, limiter: &Limiter) -> bool {
+    limiter.in_flight() == 0 || resident_bytes().is_none_or(|rss| rss <= limit)
+}
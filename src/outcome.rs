@@ -0,0 +1,38 @@
+/// The output of a future spawned in a scope created via
+/// `Scope::with_outcome_tagging`, tagged with whether the
+/// future ran to completion or was cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskOutcome<T> {
+    /// The output of a future that ran to completion.
+    Completed(T),
+
+    /// The default value of a cancellable future that was
+    /// cancelled; see `Scope::spawn_cancellable`.
+    Cancelled(T),
+}
+
+impl<T> TaskOutcome<T> {
+    /// Whether the future ran to completion.
+    #[inline]
+    pub fn is_completed(&self) -> bool { matches!(self, TaskOutcome::Completed(_)) }
+
+    /// Whether the future was cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool { matches!(self, TaskOutcome::Cancelled(_)) }
+
+    /// The value, whether the future completed or not.
+    pub fn into_inner(self) -> T {
+        match self {
+            TaskOutcome::Completed(value) => value,
+            TaskOutcome::Cancelled(value) => value,
+        }
+    }
+
+    /// The output, if the future ran to completion.
+    pub fn completed(self) -> Option<T> {
+        match self {
+            TaskOutcome::Completed(value) => Some(value),
+            TaskOutcome::Cancelled(_) => None,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::any::Any;
+use std::fmt;
+
+/// A panic caught while driving a future spawned in a
+/// `Scope`. Holds the panic payload, and the index (in
+/// order of spawning) of the future that panicked.
+pub struct CaughtPanic {
+    payload: Box<dyn Any + Send + 'static>,
+    index: Option<usize>,
+}
+
+impl CaughtPanic {
+    pub(crate) fn new(payload: Box<dyn Any + Send + 'static>,
+                      index: Option<usize>) -> Self {
+        CaughtPanic { payload, index }
+    }
+
+    /// Index (in order of spawning) of the future that
+    /// panicked, if known.
+    #[inline]
+    pub fn index(&self) -> Option<usize> { self.index }
+
+    /// The payload of the panic.
+    #[inline]
+    pub fn payload(&self) -> &(dyn Any + Send + 'static) { &*self.payload }
+
+    /// The panic message, if the payload is a string.
+    pub fn message(&self) -> Option<&str> {
+        if let Some(msg) = self.payload.downcast_ref::<&'static str>() {
+            Some(msg)
+        } else {
+            self.payload.downcast_ref::<String>().map(|msg| msg.as_str())
+        }
+    }
+
+    /// Consume and return the payload, say to continue
+    /// unwinding via `std::panic::resume_unwind`.
+    #[inline]
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> { self.payload }
+}
+
+impl fmt::Debug for CaughtPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CaughtPanic")
+            .field("message", &self.message())
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl fmt::Display for CaughtPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "spawned future #{} panicked", index)?,
+            None => write!(f, "spawned future panicked")?,
+        }
+        if let Some(msg) = self.message() {
+            write!(f, ": {}", msg)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CaughtPanic {}
@@ -0,0 +1,77 @@
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Poll, Context};
+
+use futures::Stream;
+use futures::channel::mpsc::{UnboundedSender, UnboundedReceiver};
+use futures::stream::FusedStream;
+
+/// A progress update reported by a future spawned via
+/// `Scope::spawn_with_progress`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressEvent {
+    /// The index (in order of spawning) of the future.
+    pub index: usize,
+
+    /// The progress reported, say a percentage.
+    pub value: f64,
+}
+
+/// Reports progress from a future spawned via
+/// `Scope::spawn_with_progress`. The sender carries the
+/// lifetime `'a` of the scope, and hence can not outlive
+/// it.
+#[derive(Clone)]
+pub struct ProgressSender<'a> {
+    tx: UnboundedSender<ProgressEvent>,
+    index: usize,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>
+}
+
+impl<'a> ProgressSender<'a> {
+    pub(crate) fn new(tx: UnboundedSender<ProgressEvent>, index: usize) -> Self {
+        ProgressSender { tx, index, _marker: PhantomData }
+    }
+
+    /// Report `value` to the `ProgressEvents` stream of the
+    /// scope. The events are buffered until received, and
+    /// hence this never waits; the event is discarded if
+    /// the stream has been dropped.
+    pub fn send(&self, value: f64) {
+        let _ = self.tx.unbounded_send(ProgressEvent { index: self.index, value });
+    }
+}
+
+/// A stream of the progress reported by the futures of a
+/// scope; see `Scope::progress_events`. The stream ends
+/// once the scope (and all its senders) are dropped.
+pub struct ProgressEvents {
+    rx: UnboundedReceiver<ProgressEvent>,
+}
+
+impl ProgressEvents {
+    pub(crate) fn new(rx: UnboundedReceiver<ProgressEvent>) -> Self {
+        ProgressEvents { rx }
+    }
+}
+
+impl Stream for ProgressEvents {
+    type Item = ProgressEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
+impl FusedStream for ProgressEvents {
+    fn is_terminated(&self) -> bool {
+        self.rx.is_terminated()
+    }
+}
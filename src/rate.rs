@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+use futures_timer::Delay;
+
+/// A token bucket bounding the rate at which futures are
+/// spawned by a `Scope`. It holds up to one second worth of
+/// tokens (and at least one), and starts full: thus, bursts
+/// of up to `per_second` spawns are allowed.
+pub(crate) struct TokenBucket {
+    per_second: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(per_second: f64) -> Self {
+        let mut bucket = TokenBucket { per_second, tokens: 0., last: Instant::now() };
+        bucket.tokens = bucket.capacity();
+        bucket
+    }
+
+    pub fn per_second(&self) -> f64 { self.per_second }
+
+    /// Change the rate, retaining the tokens accumulated so
+    /// far (up to the new capacity).
+    pub fn set_per_second(&mut self, per_second: f64) {
+        self.refill();
+        self.per_second = per_second;
+        self.tokens = self.tokens.min(self.capacity());
+    }
+
+    fn capacity(&self) -> f64 {
+        self.per_second.max(1.)
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity());
+        self.last = now;
+    }
+
+    /// Take a token if one is available, without waiting.
+    pub fn try_acquire(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Wait for a token, and take it.
+    pub async fn acquire(&mut self) {
+        while !self.try_acquire() {
+            // Tiny rates may overflow a `Duration`
+            let wait = (1. - self.tokens) / self.per_second;
+            Delay::new(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX)).await;
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use crate::JoinError;
+
+/// Outputs taken from the futures of a `Scope` ahead of its
+/// stream, by `Scope::peek_ready`. The stream yields these
+/// first, in the same order.
+pub(crate) struct Ready<T> {
+    outputs: VecDeque<T>,
+    indices: VecDeque<usize>,
+    // An error ends the outputs that may be peeked at
+    error: Option<(usize, JoinError)>,
+}
+
+impl<T> Ready<T> {
+    pub fn new() -> Self {
+        Ready { outputs: VecDeque::new(), indices: VecDeque::new(), error: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len() + self.error.is_some() as usize
+    }
+
+    /// Whether more outputs may be pushed: not after an
+    /// error.
+    pub fn accepts(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn push(&mut self, index: usize, output: Result<T, JoinError>) {
+        match output {
+            Ok(output) => {
+                self.outputs.push_back(output);
+                self.indices.push_back(index);
+            },
+            Err(err) => self.error = Some((index, err)),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(usize, Result<T, JoinError>)> {
+        match self.indices.pop_front() {
+            Some(index) => self.outputs.pop_front().map(|output| (index, Ok(output))),
+            None => self.error.take().map(|(index, err)| (index, Err(err))),
+        }
+    }
+
+    /// The outputs pushed so far, up to the error (if any).
+    pub fn outputs(&mut self) -> &[T] {
+        self.outputs.make_contiguous()
+    }
+}
@@ -1,142 +1,2216 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
 use std::task::{Poll, Context};
 use std::pin::Pin;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
 
-use futures::{Stream, Future, FutureExt};
-use futures::future::BoxFuture;
-use futures::stream::FuturesUnordered;
-
-use async_std::task::JoinHandle;
+use futures::{Stream, Future};
+use futures::stream::{FusedStream, ReadyChunks};
+use futures::channel::oneshot;
+use futures::future::{AbortHandle, Abortable, Aborted, BoxFuture};
 
 use pin_project::{pin_project, pinned_drop};
-use crate::Cancellation;
+use crate::{Spawner, ExecutorRef, TimingStart, ScopeHandle, SpawnHandle, ScopeAbortHandle, CaughtPanic,
+            ShutdownSummary, PartialOutputs, Elapsed, DropPolicy, Fairness, JoinError,
+            ProgressSender, ProgressEvents, TaskOutcome, CancelReason, Permits, BackpressureStats,
+            Logger, LogLine};
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+use crate::{DefaultSpawner, ScopeBuilder};
+use crate::handle::{Completion, Finished};
+use crate::tasks::Tasks;
+use crate::rate::TokenBucket;
+use crate::ready::Ready;
+use crate::tag::Tag;
+use crate::state::{self, State};
+#[cfg(feature = "tokio")]
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// The hook of `Scope::throttle_on_backpressure`.
+type BackpressureHook<'a> = dyn FnMut(BackpressureStats) -> usize + Send + 'a;
 
 /// A scope to allow controlled spawning of non 'static
 /// futures. Futures can be spawned using `spawn` or
-/// `spawn_cancellable` methods.
+/// `spawn_cancellable` methods, and are driven by the
+/// executor `Sp`.
+///
+/// The scope is a `Stream` of the outputs of the spawned
+/// futures. If a future panics, the panic is resumed when
+/// the stream yields its output; use `caught` to obtain a
+/// stream of `Result<T, CaughtPanic>` instead.
+///
+/// Dropping the scope before it is driven to completion
+/// blocks the current thread until the futures complete:
+/// by default, the cancellable futures are cancelled, and
+/// the others are waited for; see `with_drop_policy`.
+///
+/// A scope may be reused once drained: futures spawned
+/// after the stream has yielded `None` are driven, and
+/// their outputs yielded, as before. All such batches
+/// share the lifetime `'a` of the scope, hence the data
+/// borrowed by every batch must outlive the scope itself;
+/// a batch borrowing data created afterwards needs a fresh
+/// scope. Note that cancelling the scope is permanent: the
+/// cancellable futures of the later batches are cancelled
+/// too.
 ///
 /// # Safety
 ///
 /// This type uses `Drop` implementation to guarantee
 /// safety. It is not safe to forget this object unless it
-/// is driven to completion.
+/// is driven to completion. Forgetting it is not detected,
+/// not even in debug builds: the `Drop` implementation
+/// simply does not run, and the futures may then outlive
+/// the data they borrow.
+// The spawner defaults to the `DefaultSpawner`, if an
+// executor feature provides one.
 #[pin_project(PinnedDrop)]
-pub struct Scope<'a, T> {
+pub struct Scope<'a, T,
+                 #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                 #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
     done: bool,
     len: usize,
     remaining: usize,
-    cancellation: Arc<Cancellation>,
+    state: Arc<State<T, Sp::Handle<Completion<T>>>>,
+    concurrency_limit: Option<usize>,
+    backpressure: Option<Mutex<Box<BackpressureHook<'a>>>>,
+    rate_limit: Option<TokenBucket>,
+    drop_policy: DropPolicy,
+    fairness: Fairness,
+    coop_budget: Option<usize>,
+    coop_yielded: usize,
+    thread_affinity: Option<Vec<usize>>,
+    tagged: HashMap<Tag, Vec<ScopeHandle<'a, T>>>,
+    #[cfg(feature = "tokio")]
+    token: Option<(CancellationToken, DropGuard)>,
+    ready: Ready<T>,
+    spawner: Sp,
     #[pin]
-    futs: FuturesUnordered<JoinHandle<T>>,
+    futs: Tasks<Sp::Handle<Completion<T>>>,
+    deferred: Mutex<VecDeque<Deferred<'a, T, Sp>>>,
 
     // Future proof against variance changes
     _marker: PhantomData<fn(&'a ()) -> &'a ()>
 }
 
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
 impl<'a, T: Send + 'static> Scope<'a, T> {
-    /// Create a Scope object.
+    /// Create a Scope object using the `DefaultSpawner`.
+    ///
+    /// # Safety
     ///
     /// This function is unsafe as `futs` may hold futures
     /// which have to be manually driven to completion.
     pub unsafe fn create() -> Self {
+        Scope::create_with(DefaultSpawner::default())
+    }
+
+    /// A builder to configure the scope before creating it,
+    /// checking the options together; see `ScopeBuilder`.
+    /// With the default options, prefer `create`.
+    pub fn builder() -> ScopeBuilder<'a, T> {
+        ScopeBuilder::new()
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl<'a, T: Send + 'static> Scope<'a, T, crate::InlineSpawner> {
+    /// Create a Scope object for testing, that drives the
+    /// futures itself (via `InlineSpawner`), polling them
+    /// in a reproducible order derived from `seed`. Given
+    /// spawned futures that are deterministic, so is the
+    /// order of the outputs of the scope.
+    ///
+    /// The futures are driven only while the scope is
+    /// polled, on the current thread.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create_deterministic(seed: u64) -> Self {
+        let mut scope = Scope::create_with(crate::InlineSpawner);
+        scope.futs = Tasks::seeded(seed);
+        scope
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<'a, T: Send + 'static> Scope<'a, T, crate::TokioSpawner> {
+    /// Create a Scope object that spawns futures onto the
+    /// runtime of `handle`; see `TokioSpawner::with_handle`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create_with_handle(handle: tokio::runtime::Handle) -> Self {
+        Scope::create_with(crate::TokioSpawner::with_handle(handle))
+    }
+
+    /// Create a Scope object whose cancellable futures are
+    /// aborted once `token` is cancelled; see
+    /// `with_cancellation_token`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create_with_token(token: CancellationToken) -> Self {
+        Scope::create().with_cancellation_token(token)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<'a, T: Send + 'static, Sp: Spawner> Scope<'a, T, Sp> {
+    /// Abort the cancellable futures (as in `abort_all`)
+    /// once `token` is cancelled, with the reason
+    /// `CancelReason::ExternalSignal`. The token is watched
+    /// by a task spawned onto the executor (see `executor`),
+    /// that ends as the scope is dropped. Replaces the token
+    /// set previously, if any.
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        let stop = CancellationToken::new();
+        let abort = self.abort_handle();
+        let (watched, stopped) = (token.clone(), stop.clone());
+        self.spawner.spawn_detached(async move {
+            futures::future::select(
+                Box::pin(watched.cancelled()),
+                Box::pin(stopped.cancelled()),
+            ).await;
+            if watched.is_cancelled() {
+                abort.abort();
+            }
+        });
+        self.token = Some((token, stop.drop_guard()));
+        self
+    }
+
+    /// Spawn the future `f(token)`, where `token` is a child
+    /// of the token of the scope (see
+    /// `with_cancellation_token`): it is cancelled once the
+    /// token of the scope is. Without a token, `token` is
+    /// never cancelled. Unlike the cancellable futures, the
+    /// future is not aborted; it is expected to observe the
+    /// token, and return early.
+    pub fn spawn_with_token<F, Fut>(&mut self, f: F) -> ScopeHandle<'a, T>
+    where F: FnOnce(CancellationToken) -> Fut,
+          Fut: Future<Output=T> + Send + 'a
+    {
+        let token = match &self.token {
+            Some((token, _)) => token.child_token(),
+            None => CancellationToken::new(),
+        };
+        self.spawn(f(token))
+    }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> Scope<'a, T, Sp> {
+    /// Create a Scope object that spawns futures using
+    /// `spawner`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe as `futs` may hold futures
+    /// which have to be manually driven to completion.
+    pub unsafe fn create_with(spawner: Sp) -> Self {
         Scope{
             done: false,
             len: 0,
             remaining: 0,
-            cancellation: Arc::new(Cancellation::new()),
-            futs: FuturesUnordered::new(),
+            state: Arc::new(State::new()),
+            concurrency_limit: None,
+            backpressure: None,
+            rate_limit: None,
+            drop_policy: DropPolicy::default(),
+            fairness: Fairness::default(),
+            coop_budget: None,
+            coop_yielded: 0,
+            thread_affinity: None,
+            tagged: HashMap::new(),
+            #[cfg(feature = "tokio")]
+            token: None,
+            ready: Ready::new(),
+            spawner,
+            futs: Tasks::unordered(),
+            deferred: Mutex::new(VecDeque::new()),
             _marker: PhantomData,
         }
     }
 
-    /// Spawn a future with `async_std::task::spawn`. The
+    /// Yield the outputs of the futures in the order they
+    /// were spawned, instead of the order of completion.
+    /// Note that completed outputs are held back until all
+    /// the futures spawned before them complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if futures have already been spawned in this
+    /// scope.
+    pub fn with_ordering(mut self) -> Self {
+        assert!(self.futs.is_empty(), "with_ordering called on a non-empty scope");
+        self.futs = Tasks::ordered();
+        self
+    }
+
+    /// Similar to `with_ordering`, but at most `window`
+    /// futures spawned in the scope are in flight, or
+    /// completed with their outputs held back: the later
+    /// futures are not spawned onto the executor until the
+    /// outputs before them are yielded. Futures spawned via
+    /// a `SpawnHandle` are not held back. Those held back
+    /// still count towards a concurrency limit, so limited
+    /// spawns may wait on the scope being polled; and
+    /// `cancel_all` spawns them all, to cancel them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero, or if futures have
+    /// already been spawned in this scope.
+    pub fn with_ordered_window(mut self, window: usize) -> Self {
+        assert!(window > 0, "ordered window must be positive");
+        assert!(self.futs.is_empty(), "with_ordered_window called on a non-empty scope");
+        self.futs = Tasks::windowed(window);
+        self
+    }
+
+    /// Presize the collection of spawned futures to hold
+    /// `capacity` futures, say when spawning many of them.
+    /// This is purely a performance knob, and only helps
+    /// with `with_ordered_window` (or the seeded scopes of
+    /// `test-util`): the default collection, and that of
+    /// `with_ordering`, allocate each future separately.
+    /// Thus, this should be called after choosing the
+    /// ordering.
+    pub fn with_initial_capacity(mut self, capacity: usize) -> Self {
+        self.futs.reserve(capacity);
+        self
+    }
+
+    /// Return control to the executor after the stream has
+    /// yielded `budget` outputs in a row without waiting,
+    /// as in `yield_now`. This keeps a consumer of many
+    /// quickly completing futures from starving the other
+    /// tasks of the executor. By default, there is no
+    /// budget. Note that `drain_ready` also stops once the
+    /// budget is used up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    pub fn with_coop_budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0, "coop budget must be positive");
+        self.coop_budget = Some(budget);
+        self
+    }
+
+    /// Spawn the futures of this scope onto the executor
+    /// threads `worker_ids` only, say to keep them on a NUMA
+    /// node; see `Spawner::spawn_on_workers`. This is a
+    /// no-op unless the spawner supports pinning tasks,
+    /// which none of the executors supported by this crate
+    /// do. It does not apply to futures spawned via
+    /// `spawn_named`, `spawn_blocking`, or `SpawnHandle`s.
+    pub fn with_thread_affinity(mut self, worker_ids: impl IntoIterator<Item=usize>) -> Self {
+        self.thread_affinity = Some(worker_ids.into_iter().collect());
+        self
+    }
+
+    /// Yield the outputs along with the index (in order of
+    /// spawning) of their future; see `Indexed`.
+    #[inline]
+    pub fn with_indexing(self) -> Indexed<'a, T, Sp> {
+        Indexed { scope: self, assert_ordered: false, next_index: 0 }
+    }
+
+    /// Yield the outputs along with the duration of their
+    /// future, from its spawn to its completion; see
+    /// `Timed`. Only the futures spawned hereafter are
+    /// timed.
+    #[inline]
+    pub fn with_timing(self) -> Timed<'a, T, Sp> {
+        self.with_timing_from(TimingStart::Spawn)
+    }
+
+    /// Similar to `with_timing`, but the futures are timed
+    /// from `start`; see `TimingStart`.
+    pub fn with_timing_from(self, start: TimingStart) -> Timed<'a, T, Sp> {
+        self.state.set_timing(start);
+        Timed { scope: self }
+    }
+
+    /// Name recorded (as the `scope.name` field) in the
+    /// spans of the futures spawned hereafter; see the
+    /// `tracing` feature. The span itself is named
+    /// `scope.task`, as span names must be known statically.
+    #[cfg(feature = "tracing")]
+    pub fn with_span_name(self, name: &'static str) -> Self {
+        self.state.set_span_name(name);
+        self
+    }
+
+    /// What to do with the pending futures if the scope is
+    /// dropped before being driven to completion; see
+    /// `DropPolicy`.
+    pub fn with_drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// Which of the spawns waiting for capacity, and the
+    /// consumer of the outputs, to favour when the
+    /// concurrency limit frees up; see `Fairness`.
+    pub fn with_fairness(mut self, fairness: Fairness) -> Self {
+        self.fairness = fairness;
+        self
+    }
+
+    /// Yield a `Result<T, JoinError>` for each future,
+    /// distinguishing the futures that panicked, or were
+    /// aborted, from those that completed; see `Joined`.
+    #[inline]
+    pub fn with_join_errors(self) -> Joined<'a, T, Sp> {
+        Joined { scope: self }
+    }
+
+    /// Whether a panic in any of the futures spawned
+    /// hereafter aborts its siblings, as in `abort_all`
+    /// (disabled by default). Say, to fail fast: the panic
+    /// is then resumed by the stream (or `collect`) as
+    /// usual, while the cancellable siblings have already
+    /// been cancelled.
+    pub fn with_abort_on_panic(self, abort: bool) -> Self {
+        self.state.set_abort_on_panic(abort);
+        self
+    }
+
+    /// Register a callback invoked each time a spawned
+    /// future panics, with the index of the future (in
+    /// order of spawning) and the panic payload, say for
+    /// logging. The panic is still yielded (or resumed) as
+    /// usual. This replaces any previous hook, and applies
+    /// to the futures already spawned as well. Only caught
+    /// panics are reported; see `catch_panics`.
+    ///
+    /// The hook is invoked on the executor thread that
+    /// drove the future, and should not panic.
+    pub fn with_panic_hook<F>(self, hook: F) -> Self
+    where F: Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static
+    {
+        self.state.set_panic_hook(Some(Arc::new(hook)));
+        self
+    }
+
+    /// Bound the depth of the handles obtained via
+    /// `SpawnHandle::spawn_nested`, which fails beyond it.
+    pub fn with_max_depth(self, depth: usize) -> Self {
+        self.state.set_max_depth(depth);
+        self
+    }
+
+    /// Bound the number of futures being driven
+    /// concurrently when spawning via `spawn_limited`, or
+    /// their total weight when spawning via
+    /// `spawn_weighted`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero.
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.set_concurrency_limit(limit);
+        self
+    }
+
+    /// Change the concurrency limit; see
+    /// `with_concurrency_limit`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero.
+    pub fn set_concurrency_limit(&mut self, limit: usize) {
+        assert!(limit > 0, "concurrency limit must be positive");
+        self.concurrency_limit = Some(limit);
+    }
+
+    /// Call `hook` whenever a spawn via `spawn_limited` (or
+    /// `spawn_weighted`, and the like) has to wait for the
+    /// concurrency limit, before waiting: the limit is set
+    /// to the returned one, say to adapt it to the signals
+    /// of the downstream (as in AIMD). A zero limit is taken
+    /// as one. The hook is not called without a limit, nor
+    /// while the scope is paused.
+    pub fn throttle_on_backpressure<F>(mut self, hook: F) -> Self
+    where F: FnMut(BackpressureStats) -> usize + Send + 'a
+    {
+        self.backpressure = Some(Mutex::new(Box::new(hook)));
+        self
+    }
+
+    /// Bound the concurrency to `initial`, to be adapted by
+    /// `hook` as the spawns have to wait for it; see
+    /// `with_concurrency_limit`, and
+    /// `throttle_on_backpressure`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `initial` is zero.
+    pub fn with_adaptive_concurrency<F>(self, initial: usize, hook: F) -> Self
+    where F: FnMut(BackpressureStats) -> usize + Send + 'a
+    {
+        self.with_concurrency_limit(initial).throttle_on_backpressure(hook)
+    }
+
+    /// Bound the rate at which futures are spawned via
+    /// `spawn_limited` (and the other limited spawns) to
+    /// `per_second`, which may be fractional. This is
+    /// independent of the concurrency limit: it bounds how
+    /// often futures start, rather than how many run at
+    /// once. Bursts of up to `per_second` spawns are
+    /// allowed after a pause.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_second` is not positive, or not
+    /// finite.
+    pub fn with_rate_limit(mut self, per_second: f64) -> Self {
+        self.set_rate_limit(per_second);
+        self
+    }
+
+    /// Change the rate limit; see `with_rate_limit`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_second` is not positive, or not
+    /// finite.
+    pub fn set_rate_limit(&mut self, per_second: f64) {
+        assert!(per_second > 0. && per_second.is_finite(), "rate limit must be positive and finite");
+        match &mut self.rate_limit {
+            Some(bucket) => bucket.set_per_second(per_second),
+            None => self.rate_limit = Some(TokenBucket::new(per_second)),
+        }
+    }
+
+    /// Spawn a future with the executor's `spawn`. The
     /// future is expected to be driven to completion before
     /// 'a expires.
-    pub fn spawn<F: Future<Output=T> + Send + 'a>(&mut self, f: F) {
-        let handle = async_std::task::spawn(unsafe {
-            std::mem::transmute::<_, BoxFuture<'static, T>>(f.boxed())
+    ///
+    /// The returned handle may be used to abort the future,
+    /// or await its output. If the handle is dropped, the
+    /// output is yielded by the stream instead.
+    ///
+    /// This does not respect the concurrency limit; use
+    /// `spawn_limited` for that.
+    pub fn spawn<F: Future<Output=T> + Send + 'a>(&mut self, f: F)
+                                                 -> ScopeHandle<'a, T> {
+        let (task, handle) = self.state.wrap(f, true);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Similar to `spawn`, but the future is named `name`
+    /// where the spawner supports it (see
+    /// `Spawner::spawn_named`). Under `tokio`, built with
+    /// `--cfg tokio_unstable` and the `tracing` feature,
+    /// the name is visible in `tokio-console`; other
+    /// spawners ignore it.
+    pub fn spawn_named<F: Future<Output=T> + Send + 'a>(&mut self, name: impl Into<String>, f: F)
+                                                       -> ScopeHandle<'a, T> {
+        let (task, handle) = self.state.wrap(f, true);
+        let name = name.into();
+        self.spawn_deferrable(Box::new(move |spawner, _| unsafe {
+            state::spawn_task_named(spawner, &name, task)
+        }));
+        handle
+    }
+
+    /// Spawn a future whose output is of a different type
+    /// `U`, which is mapped into the output type `T` of the
+    /// scope via `map`. Say, `T` may be an enum of the
+    /// outputs of heterogeneous futures:
+    ///
+    /// ``` rust
+    /// enum Output {
+    ///     Name(String),
+    ///     Count(u32),
+    /// }
+    ///
+    /// # #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+    /// # {
+    /// # #[cfg(feature = "tokio")]
+    /// # let runtime = tokio::runtime::Runtime::new().unwrap();
+    /// # #[cfg(feature = "tokio")]
+    /// # let _guard = runtime.enter();
+    /// let words = vec!["hello", "world"];
+    /// let (_, outputs) = async_scoped::scope_and_block(|s| {
+    ///     s.spawn_mapped(async { words.join(" ") }, Output::Name);
+    ///     s.spawn_mapped(async { words.len() as u32 }, Output::Count);
+    /// });
+    /// assert_eq!(outputs.len(), 2);
+    /// # }
+    /// ```
+    #[inline]
+    pub fn spawn_mapped<U, F, M>(&mut self, f: F, map: M) -> ScopeHandle<'a, T>
+    where F: Future<Output=U> + Send + 'a,
+          M: FnOnce(U) -> T + Send + 'a
+    {
+        self.spawn(async move { map(f.await) })
+    }
+
+    /// Spawn a future that is already pinned on the heap;
+    /// see `spawn`. Only the box is moved into the task,
+    /// and not the future itself, which helps with large
+    /// futures, or when the allocation is made ahead of
+    /// time.
+    ///
+    /// The future may borrow data of lifetime 'a, as with
+    /// `spawn`, and hence the box must not be leaked out of
+    /// the scope some other way: it is dropped by the
+    /// executor once the future completes (or is aborted).
+    #[inline]
+    pub fn spawn_pinned(&mut self, f: Pin<Box<dyn Future<Output=T> + Send + 'a>>)
+                        -> ScopeHandle<'a, T> {
+        self.spawn(f)
+    }
+
+    /// Spawn the future returned by `f`, say an async
+    /// closure, as `spawn(f())`. The closure is invoked
+    /// immediately, on the current thread.
+    #[inline]
+    pub fn spawn_fn<F, Fu>(&mut self, f: Fu) -> ScopeHandle<'a, T>
+    where F: Future<Output=T> + Send + 'a,
+          Fu: FnOnce() -> F
+    {
+        self.spawn(f())
+    }
+
+    /// Spawn an already boxed future, say one of a queue of
+    /// futures of different types, as `spawn(fut)`. The
+    /// future must outlive 'a as usual, which the type
+    /// enforces; `spawn` itself also accepts a `BoxFuture`.
+    #[inline]
+    pub fn spawn_boxed(&mut self, fut: BoxFuture<'a, T>) -> ScopeHandle<'a, T> {
+        self.spawn(fut)
+    }
+
+    /// Spawn a future that starts once the futures of
+    /// `depends_on` are done, say the upstream steps of a
+    /// build graph. If any of them failed (panicked, or was
+    /// aborted), the future is aborted before it starts, as
+    /// in `ScopeHandle::abort`: its output is not yielded,
+    /// and it counts as failed in turn, cascading to its own
+    /// dependents. The handles may still be awaited (or
+    /// dropped) as usual. An upstream `Err` output does not
+    /// count as a failure here; see `spawn_dependent_ok`.
+    pub fn spawn_dependent<F>(&mut self, depends_on: &[&ScopeHandle<'a, T>], f: F) -> ScopeHandle<'a, T>
+    where F: Future<Output=T> + Send + 'a
+    {
+        let dependencies = depends_on.iter().map(|handle| handle.finished()).collect();
+        self.spawn_gated(dependencies, f)
+    }
+
+    // Spawn `f` once `dependencies` are done, aborting it
+    // if any of them failed.
+    fn spawn_gated<F>(&mut self, dependencies: Vec<Finished<T>>, f: F) -> ScopeHandle<'a, T>
+    where F: Future<Output=T> + Send + 'a
+    {
+        let abort = Arc::new(std::sync::OnceLock::<AbortHandle>::new());
+        let own_abort = abort.clone();
+        let gated = async move {
+            if futures::future::join_all(dependencies).await.contains(&true) {
+                own_abort.get().expect("abort handle not set").abort();
+                // Dropped once polled again by `WithAbort`
+                futures::future::pending::<()>().await;
+            }
+            f.await
+        };
+        let (task, handle) = self.state.wrap(gated, true);
+        let _ = abort.set(handle.abort_handle());
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn a future whose output is sent to the returned
+    /// receiver, instead of being yielded by the stream.
+    /// The future is still driven by the scope, and counts
+    /// towards `remaining` until the stream reaches it. If
+    /// the receiver is dropped before the future completes,
+    /// or the future panics,
+    /// the output (or panic) is yielded by the stream as
+    /// usual.
+    pub fn spawn_with_receiver<F>(&mut self, f: F) -> oneshot::Receiver<T>
+    where F: Future<Output=T> + Send + 'a
+    {
+        let (tx, rx) = oneshot::channel();
+        let (task, handle) = self.state.wrap(f, false);
+        let task = async move {
+            let completion = task.await;
+            if !tx.is_canceled() {
+                if let Some(output) = handle.take_output() {
+                    let _ = tx.send(output);
+                }
+            }
+            completion
+        };
+        self.spawn_task(task);
+        rx
+    }
+
+    /// Spawn a future tagged with `tag`, to be awaited along
+    /// with the other futures of the same tag via
+    /// `drain_tag`. Tags may be of any type; tags of
+    /// different types never match. The outputs of tagged
+    /// futures are yielded by `drain_tag`; those not yet
+    /// drained are yielded by the stream once all the other
+    /// outputs have been, before it reports done.
+    pub fn spawn_tagged<K, F>(&mut self, tag: K, f: F)
+    where K: Hash + Eq + Send + Sync + 'static,
+          F: Future<Output=T> + Send + 'a
+    {
+        let handle = self.spawn(f);
+        self.tagged.entry(Tag::new(tag)).or_default().push(handle);
+    }
+
+    /// Wait for the futures spawned with `tag` (see
+    /// `spawn_tagged`) alone, and return their outputs in
+    /// order of spawning. The other futures keep running,
+    /// and are yielded by the stream as usual. Panics are
+    /// resumed as for `ScopeHandle`.
+    pub async fn drain_tag<K>(&mut self, tag: K) -> Vec<T>
+    where K: Hash + Eq + Send + Sync + 'static
+    {
+        let handles = self.tagged.remove(&Tag::new(tag)).unwrap_or_default();
+        futures::future::join_all(handles).await
+    }
+
+    /// Spawn the future `f(tx)`, where `tx` may be used by
+    /// the future to report its progress (say, a
+    /// percentage) to the `progress_events` stream of the
+    /// scope. The events are tagged with the index of the
+    /// future, and are separate from its output, which is
+    /// yielded by the scope as usual.
+    pub fn spawn_with_progress<F, Fu>(&mut self, f: Fu) -> ScopeHandle<'a, T>
+    where F: Future<Output=T> + Send + 'a,
+          Fu: FnOnce(ProgressSender<'a>) -> F
+    {
+        let index = self.state.reserve_index();
+        let f = f(ProgressSender::new(self.state.progress_tx(), index));
+        let (task, handle) = self.state.wrap_at(index, f, true, 1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn the future `f(logger)`, where `logger` may be
+    /// used by the future to log lines to the buffer of the
+    /// scope, taken via `take_logs`. The lines are tagged
+    /// with the index of the future, and are separate from
+    /// its output, which is yielded by the scope as usual.
+    pub fn spawn_logged<F, Fu>(&mut self, f: Fu) -> ScopeHandle<'a, T>
+    where F: Future<Output=T> + Send + 'a,
+          Fu: FnOnce(Logger<'a>) -> F
+    {
+        let index = self.state.reserve_index();
+        let f = f(Logger::new(self.state.logs(), index));
+        let (task, handle) = self.state.wrap_at(index, f, true, 1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Take the lines logged so far by the futures spawned
+    /// via `spawn_logged`, in the order they were logged;
+    /// the buffer is emptied.
+    pub fn take_logs(&self) -> Vec<LogLine> {
+        self.state.take_logs()
+    }
+
+    /// Spawn each of the futures in `futs`; see `spawn`.
+    /// Their outputs are yielded by the stream. This does
+    /// not respect the concurrency limit; use
+    /// `spawn_all_limited` for that.
+    pub fn spawn_all<I>(&mut self, futs: I)
+    where I: IntoIterator, I::Item: Future<Output=T> + Send + 'a
+    {
+        for f in futs {
+            self.spawn(f);
+        }
+    }
+
+    /// Spawn a blocking closure with the executor's
+    /// `spawn_blocking`. Similar to `spawn`, the closure
+    /// may borrow data of lifetime 'a, and its output is
+    /// yielded by the stream.
+    ///
+    /// A running closure can not be interrupted, and hence
+    /// aborting via the returned handle (or cancelling the
+    /// scope) has no effect on it.
+    pub fn spawn_blocking<F: FnOnce() -> T + Send + 'a>(&mut self, f: F)
+                                                       -> ScopeHandle<'a, T>
+    where Sp::Handle<T>: Send + 'static
+    {
+        let f: Box<dyn FnOnce() -> T + Send + 'a> = Box::new(f);
+        let join = self.spawner.spawn_blocking(unsafe {
+            std::mem::transmute::<Box<dyn FnOnce() -> T + Send + 'a>,
+                                  Box<dyn FnOnce() -> T + Send + 'static>>(f)
         });
-        self.futs.push(handle);
-        self.len += 1;
-        self.remaining += 1;
+
+        // The join handle must not be dropped before the
+        // closure completes, hence this is not abortable.
+        let (task, handle) = self.state.wrap(join, false);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn a blocking closure, as in `spawn_blocking`,
+    /// that is cancellable until it starts. If the scope is
+    /// cancelled before the closure is started by the
+    /// blocking pool, the closure is skipped, and
+    /// `fallback()` is yielded instead. Blocking work that
+    /// has already started can not be interrupted: it runs
+    /// to completion, and its output is yielded as usual.
+    ///
+    /// Note that the scope still waits for the blocking pool
+    /// to get to a skipped closure, which then returns
+    /// immediately.
+    pub fn spawn_blocking_cancellable<F, Fu>(&mut self, f: F, fallback: Fu)
+                                             -> ScopeHandle<'a, T>
+    where F: FnOnce() -> T + Send + 'a,
+          Fu: FnOnce() -> T + Send + 'a,
+          Sp::Handle<Option<T>>: Send + 'static
+    {
+        // The closure is skipped if it starts after the
+        // cancellation.
+        let cancellation = self.state.cancellation().clone();
+        let f = move || if cancellation.is_signalled() {
+            cancellation.mark_cancelled();
+            None
+        } else {
+            Some(f())
+        };
+
+        let f: Box<dyn FnOnce() -> Option<T> + Send + 'a> = Box::new(f);
+        let join = self.spawner.spawn_blocking(unsafe {
+            std::mem::transmute::<Box<dyn FnOnce() -> Option<T> + Send + 'a>,
+                                  Box<dyn FnOnce() -> Option<T> + Send + 'static>>(f)
+        });
+        let task = async move {
+            join.await.unwrap_or_else(fallback)
+        };
+
+        // Started blocking work can not be aborted.
+        let (task, handle) = self.state.wrap(task, false);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn a wrapped future, and track it in this scope.
+    fn spawn_task<F>(&mut self, task: F)
+    where F: Future<Output=Completion<T>> + Send + 'a
+    {
+        self.spawn_deferrable(Box::new(move |spawner, workers| match workers {
+            Some(workers) => unsafe { state::spawn_task_on(spawner, workers, task) },
+            None => unsafe { state::spawn_task(spawner, task) },
+        }));
+    }
+
+    /// Spawn a wrapped future via `spawn`, unless the
+    /// window of `with_ordered_window` is full: then it is
+    /// held back, and spawned once the window moves.
+    fn spawn_deferrable(&mut self, spawn: Deferred<'a, T, Sp>) {
+        if let Some(window) = self.futs.window() {
+            let deferred = self.deferred.get_mut().unwrap_or_else(PoisonError::into_inner);
+            if self.futs.len() >= window || !deferred.is_empty() {
+                deferred.push_back(spawn);
+                self.done = false;
+                return;
+            }
+        }
+        let join = spawn(&self.spawner, self.thread_affinity.as_deref());
+        self.push(join);
     }
 
-    /// Spawn a cancellable future with `async_std::task::spawn`
+    /// A handle to spawn futures into this scope from
+    /// within the spawned futures, say, to spawn
+    /// recursively. The scope is driven to completion only
+    /// once the futures spawned via the handle complete.
+    pub fn handle(&self) -> SpawnHandle<'a, T, Sp>
+    where Sp: Clone
+    {
+        SpawnHandle::new(self.state.clone(), self.spawner.clone())
+    }
+
+    /// Spawn a future once fewer than the concurrency limit
+    /// of futures are being driven. The outputs of the
+    /// completed futures are retained by the scope while
+    /// waiting. Equivalent to `spawn` if no limit (nor rate
+    /// limit) is set, and the scope is not paused (see
+    /// `pause`).
+    pub async fn spawn_limited<F: Future<Output=T> + Send + 'a>(&mut self, f: F)
+                                                               -> ScopeHandle<'a, T> {
+        self.spawn_weighted(1, f).await
+    }
+
+    /// Spawn each of the futures in `futs` via
+    /// `spawn_limited`, waiting as needed between spawns.
+    pub async fn spawn_all_limited<I>(&mut self, futs: I)
+    where I: IntoIterator, I::Item: Future<Output=T> + Send + 'a
+    {
+        for f in futs {
+            self.spawn_limited(f).await;
+        }
+    }
+
+    /// Similar to `spawn_limited`, but instead of waiting
+    /// for the concurrency limit, `f` is returned back as
+    /// an `Err` if the scope is at its limit (or paused, or
+    /// out of its rate limit), say to shed load. Without
+    /// such limits, this always spawns.
+    pub fn try_spawn<F: Future<Output=T> + Send + 'a>(&mut self, f: F)
+                                                     -> Result<ScopeHandle<'a, T>, F> {
+        if !self.state.limiter.is_below(self.concurrency_limit, 1) {
+            return Err(f);
+        }
+        if let Some(bucket) = &mut self.rate_limit {
+            if !bucket.try_acquire() {
+                return Err(f);
+            }
+        }
+        let (task, handle) = self.state.wrap_weighted(f, true, 1);
+        self.spawn_task(task);
+        Ok(handle)
+    }
+
+    /// Reserve `n` permits of the concurrency limit, once
+    /// they fit in it (as for `spawn_weighted` of weight
+    /// `n`): until spawned via `spawn_reserved`, the permits
+    /// count towards the limit, and hence may not be taken
+    /// by the other spawns, say of other producers sharing
+    /// the scope. Dropping the returned `Permits` releases
+    /// the unused permits. Without a concurrency limit, this
+    /// does not wait (unless the scope is paused).
+    pub async fn reserve(&self, n: usize) -> Permits {
+        let (limit, limiter) = (self.concurrency_limit, &self.state.limiter);
+        futures::future::poll_fn(|cx| limiter.poll_reserve(limit, n, cx)).await;
+        Permits::new(limiter.clone(), n)
+    }
+
+    /// Spawn a future consuming one of `permits`, without
+    /// waiting for the concurrency (or rate) limit; see
+    /// `reserve`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no permits are left, or if they were
+    /// reserved from another scope.
+    pub fn spawn_reserved<F: Future<Output=T> + Send + 'a>(&mut self, permits: &Permits, f: F)
+                                                          -> ScopeHandle<'a, T> {
+        assert!(permits.is_from(&self.state.limiter), "permits reserved from another scope");
+        assert!(permits.take(), "no permits left");
+        let (task, handle) = self.state.wrap_weighted(f, true, 1);
+        // The future has entered the limiter: release its
+        // permit only now, so as not to let other spawns in.
+        self.state.limiter.unreserve(1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Similar to `spawn_limited`, but the future counts
+    /// `weight` (instead of one) towards the concurrency
+    /// limit, until it completes. Thus, the limit bounds
+    /// the total weight of the futures being driven, say
+    /// their memory footprint. A future heavier than the
+    /// limit is spawned once no futures are being driven.
+    pub async fn spawn_weighted<F: Future<Output=T> + Send + 'a>(&mut self, weight: usize, f: F)
+                                                                -> ScopeHandle<'a, T> {
+        self.on_backpressure(weight);
+        let (limit, limiter) = (self.concurrency_limit, &self.state.limiter);
+        let mut yielded = self.fairness == Fairness::PreferSpawn;
+        loop {
+            let mut waited = false;
+            futures::future::poll_fn(|cx| {
+                let poll = limiter.poll_below(limit, weight, cx);
+                waited |= poll.is_pending();
+                poll
+            }).await;
+            if !waited || yielded { break; }
+            yield_now().await;
+            yielded = true;
+        }
+        if let Some(bucket) = &mut self.rate_limit {
+            bucket.acquire().await;
+        }
+        let (task, handle) = self.state.wrap_weighted(f, true, weight);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Call the hook of `throttle_on_backpressure`, if a
+    /// future of weight `weight` has to wait.
+    fn on_backpressure(&mut self, weight: usize) {
+        let limiter = &self.state.limiter;
+        if let (Some(limit), Some(hook)) = (self.concurrency_limit, &mut self.backpressure) {
+            if limiter.is_paused() || limiter.is_below(Some(limit), weight) {
+                return;
+            }
+            let stats = BackpressureStats {
+                in_flight: limiter.in_flight(),
+                weight: limiter.weight(),
+                waiting: weight,
+                limit,
+            };
+            let limit = (hook.get_mut().unwrap_or_else(PoisonError::into_inner))(stats);
+            self.concurrency_limit = Some(limit.max(1));
+        }
+    }
+
+    /// Similar to `spawn_weighted`, but the total weight is
+    /// bounded by `limit_bytes` (instead of the concurrency
+    /// limit), and so is the resident set size of the
+    /// process: spawning waits until both allow the future
+    /// to be driven, re-reading the latter periodically.
+    /// As in `spawn_weighted`, the future is spawned anyway
+    /// once no futures are being driven.
+    ///
+    /// Reading the resident set size requires the
+    /// `memory-stats` feature; without it (or if it can not
+    /// be read on the platform), only the weight is bounded.
+    pub async fn spawn_until_memory<F: Future<Output=T> + Send + 'a>(
+        &mut self, limit_bytes: usize, weight: usize, f: F
+    ) -> ScopeHandle<'a, T> {
+        let limiter = &self.state.limiter;
+        loop {
+            futures::future::poll_fn(|cx| limiter.poll_below(Some(limit_bytes), weight, cx)).await;
+            if crate::memory::is_below(limit_bytes, limiter) { break; }
+            futures_timer::Delay::new(crate::memory::POLL_INTERVAL).await;
+        }
+        if let Some(bucket) = &mut self.rate_limit {
+            bucket.acquire().await;
+        }
+        let (task, handle) = self.state.wrap_weighted(f, true, weight);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn a cancellable future with the executor's `spawn`.
     ///
     /// The future is cancelled if the `Scope` is dropped
     /// pre-maturely. It can also be cancelled by explicitly
     /// calling (and awaiting) the `cancel` method.
+    ///
+    /// `default` is called exactly once if the future is
+    /// cancelled, and never otherwise: if the future
+    /// completes, it is dropped along with the future.
+    /// Thus, it may hold resources to be used only on
+    /// cancellation.
     #[inline]
     pub fn spawn_cancellable<F: Future<Output=T> + Send + 'a,
                              Fu: FnOnce() -> T + Send + 'a>(
         &mut self, f: F, default: Fu
-    ) {
-        self.spawn(crate::CancellableFuture::new(
-            self.cancellation.clone(), f, default
+    ) -> ScopeHandle<'a, T> {
+        self.spawn_cancellable_with_reason(f, move |_| default())
+    }
+
+    /// Similar to `spawn_cancellable`, but `default` is
+    /// passed why the future was cancelled; see
+    /// `CancelReason`.
+    #[inline]
+    pub fn spawn_cancellable_with_reason<F: Future<Output=T> + Send + 'a,
+                                         Fu: FnOnce(CancelReason) -> T + Send + 'a>(
+        &mut self, f: F, default: Fu
+    ) -> ScopeHandle<'a, T> {
+        let index = self.state.reserve_index();
+        let f = crate::CancellableFuture::new(
+            self.state.cancellation().clone(), f, default
+        ).with_index(index);
+        let (task, handle) = self.state.wrap_at(index, f, true, 1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Similar to `spawn_cancellable_with_reason`, but the
+    /// future is also cancelled if it does not complete
+    /// within `dur`: `default` is then passed
+    /// `CancelReason::Timeout`. A timed out future is
+    /// accounted as cancelled.
+    pub fn spawn_timeout_with_reason<F: Future<Output=T> + Send + 'a,
+                                     Fu: FnOnce(CancelReason) -> T + Send + 'a>(
+        &mut self, dur: Duration, f: F, default: Fu
+    ) -> ScopeHandle<'a, T> {
+        let index = self.state.reserve_index();
+        let f = crate::CancellableFuture::new(
+            self.state.cancellation().clone(), f, default
+        ).with_index(index).with_deadline(dur);
+        let (task, handle) = self.state.wrap_at(index, f, true, 1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Similar to `spawn_cancellable_with_reason`, but the
+    /// default value is obtained from the future returned
+    /// by `cleanup`, say to release resources
+    /// asynchronously. The cleanup is driven in place of the
+    /// cancelled future, and hence the scope is driven to
+    /// completion (or dropped) only once it completes; it is
+    /// not itself cancellable.
+    pub fn spawn_cancellable_async<F: Future<Output=T> + Send + 'a,
+                                   Fu: FnOnce(CancelReason) -> C + Send + 'a,
+                                   C: Future<Output=T> + Send + 'a>(
+        &mut self, f: F, cleanup: Fu
+    ) -> ScopeHandle<'a, T> {
+        let index = self.state.reserve_index();
+        let f = crate::CancellableFuture::new(
+            self.state.cancellation().clone(), async move { Ok(f.await) }, Err
+        ).with_index(index);
+        let f = async move {
+            match f.await {
+                Ok(output) => output,
+                Err(reason) => cleanup(reason).await,
+            }
+        };
+        let (task, handle) = self.state.wrap_at(index, f, true, 1);
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn a future that is raced against `cancel`: if
+    /// `cancel` resolves first, the future is dropped, and
+    /// the output of `cancel` is yielded instead. Say,
+    /// `cancel` may await a shutdown signal. This is
+    /// independent of the cancellation of the scope (see
+    /// `spawn_cancellable`).
+    #[inline]
+    pub fn spawn_with_cancel<F: Future<Output=T> + Send + 'a,
+                             C: Future<Output=T> + Send + 'a>(
+        &mut self, f: F, cancel: C
+    ) -> ScopeHandle<'a, T> {
+        self.spawn(crate::WithCancel::new(
+            self.state.cancellation().clone(), f, cancel
         ))
     }
 }
 
-impl<'a, T> Scope<'a, T> {
-    /// Cancel all futures spawned with cancellation.
+impl<'a, T: Send + 'static, Sp: Spawner> Scope<'a, Result<T, Elapsed>, Sp> {
+    /// Spawn a future that is dropped if it does not
+    /// complete within `dur`, yielding `Err(Elapsed)`
+    /// instead. Similar to `spawn_cancellable`, the future
+    /// is also cancelled (yielding `Err(Elapsed)`) when the
+    /// scope is cancelled; a timed out future is accounted
+    /// as cancelled too. See `spawn_timeout_with_reason` to
+    /// tell the two apart.
+    pub fn spawn_timeout<F: Future<Output=T> + Send + 'a>(&mut self, dur: Duration, f: F)
+                                                         -> ScopeHandle<'a, Result<T, Elapsed>> {
+        self.spawn_timeout_with_reason(dur, async { Ok(f.await) }, |_| Err(Elapsed::new()))
+    }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> Scope<'a, Result<T, Aborted>, Sp> {
+    /// Spawn a future that resolves to `Err(Aborted)` if
+    /// the returned `AbortHandle` is triggered before it
+    /// completes; the future is then dropped the next time
+    /// it is polled. Unlike `ScopeHandle::abort`, the
+    /// output of the aborted future is still yielded by the
+    /// stream (as an `Err`), and unlike `spawn_cancellable`,
+    /// the future is aborted individually, and is not
+    /// affected by the cancellation of the scope.
+    pub fn spawn_abortable<F: Future<Output=T> + Send + 'a>(&mut self, f: F) -> AbortHandle {
+        let (abort, registration) = AbortHandle::new_pair();
+        self.spawn(Abortable::new(f, registration));
+        abort
+    }
+}
+
+impl<'a, T: Send + 'static, E: Send + 'static, Sp: Spawner> Scope<'a, Result<T, E>, Sp> {
+    /// Similar to `spawn_dependent`, but a dependency that
+    /// yields an `Err` also counts as failed: the future is
+    /// then aborted before it starts, cascading to its own
+    /// dependents. The `Err` itself is still yielded (or
+    /// sent to its handle) as usual.
+    pub fn spawn_dependent_ok<F>(&mut self, depends_on: &[&ScopeHandle<'a, Result<T, E>>], f: F)
+                                 -> ScopeHandle<'a, Result<T, E>>
+    where F: Future<Output=Result<T, E>> + Send + 'a
+    {
+        let dependencies = depends_on.iter()
+            .map(|handle| handle.finished_or_failed_with(Result::is_err))
+            .collect();
+        self.spawn_gated(dependencies, f)
+    }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> Scope<'a, TaskOutcome<T>, Sp> {
+    /// Tag the outputs of the futures with whether they ran
+    /// to completion, or were cancelled (and yielded their
+    /// default value); see `Tagged`.
+    #[inline]
+    pub fn with_outcome_tagging(self) -> Tagged<'a, T, Sp> {
+        Tagged { scope: self }
+    }
+}
+
+impl<'a, T, Sp: Spawner> Scope<'a, T, Sp> {
+    /// Track a spawned future in this scope.
+    fn push(&mut self, join: Sp::Handle<Completion<T>>) {
+        self.futs.push(join);
+        self.done = false;
+        self.len += 1;
+        self.remaining += 1;
+    }
+
+    /// Cancel all futures spawned with cancellation. Once
+    /// this resolves, none of the cancellable futures are
+    /// being polled, or will be polled again; they may not
+    /// have been dropped yet though: see `cancel_all`.
     #[inline]
     pub async fn cancel(&self) {
-        self.cancellation.cancel().await;
+        self.cancel_with(CancelReason::AbortAll).await;
+    }
+
+    /// Cancel as in `cancel`, for the given reason.
+    async fn cancel_with(&self, reason: CancelReason) {
+        self.state.cancellation().cancel(reason).await;
+    }
+
+    /// Cancel all futures spawned with cancellation (as in
+    /// `cancel`), and wait until every one of them has been
+    /// dropped, running its cleanup. The futures are
+    /// dropped by the executor (as their tasks complete),
+    /// and hence the scope need not be polled meanwhile.
+    ///
+    /// In contrast, `abort_all` only signals the
+    /// cancellation, without waiting.
+    pub async fn cancel_all(&self) {
+        self.cancel().await;
+        // The futures held back by an ordered window are
+        // spawned (via the handles path), to be cancelled.
+        let deferred = std::mem::take(&mut *self.deferred.lock().unwrap_or_else(PoisonError::into_inner));
+        for spawn in deferred {
+            self.state.push_incoming(|| spawn(&self.spawner, self.thread_affinity.as_deref()));
+        }
+        let state = &self.state;
+        futures::future::poll_fn(|cx| state.poll_idle(cx)).await;
     }
 
-    /// Total number of futures spawned in this scope.
+    /// Abort all futures spawned with cancellation, without
+    /// waiting. Each pending cancellable future is woken,
+    /// and yields its default value on its next poll; other
+    /// futures are left to run to completion. Cancellable
+    /// futures spawned hereafter are aborted immediately.
+    #[inline]
+    pub fn abort_all(&self) {
+        self.state.cancellation().signal(CancelReason::AbortAll);
+    }
+
+    /// Cancel the cancellable future of index `index` (in
+    /// order of spawning, see `with_indexing`) alone: it is
+    /// woken, and yields its default value on its next poll,
+    /// as if the scope was cancelled. Returns whether the
+    /// future was still pending. Returns `false` for the
+    /// futures that are not cancellable, including those
+    /// spawned via `SpawnHandle`s.
+    #[inline]
+    pub fn cancel_one(&self, index: usize) -> bool {
+        self.state.cancellation().cancel_one(index)
+    }
+
+    /// Abort the pending futures that are not cancellable
+    /// too, as in `ScopeHandle::abort`; see
+    /// `DropPolicy::AbortAll`.
+    pub(crate) fn abort_pending(&self) {
+        self.state.abort_pending();
+    }
+
+    /// A handle to abort the futures of this scope (as in
+    /// `abort_all`) from elsewhere, say another task; see
+    /// `ScopeAbortHandle`.
+    pub fn abort_handle(&self) -> ScopeAbortHandle {
+        ScopeAbortHandle::new(self.state.cancellation())
+    }
+
+    /// Whether to catch panics in the futures spawned
+    /// hereafter (enabled by default). Caught panics are
+    /// surfaced by the scope: see `caught`. If disabled, a
+    /// panic is left to the executor to deal with, and the
+    /// output of the future is lost.
+    #[inline]
+    pub fn catch_panics(&mut self, catch: bool) {
+        self.state.set_catch_panics(catch);
+    }
+
+    /// Register a callback invoked each time a spawned
+    /// future completes (or panics, if panics are caught),
+    /// with the index of the future (in order of spawning)
+    /// and the time elapsed since it was spawned. This
+    /// replaces any previous callback, and applies to the
+    /// futures already spawned as well.
+    ///
+    /// The callback is invoked on the executor thread that
+    /// completed the future, before its output is yielded,
+    /// and hence should be quick; it should not panic.
+    pub fn on_complete<F>(&mut self, callback: F)
+    where F: Fn(usize, Duration) + Send + Sync + 'static
+    {
+        self.state.set_on_complete(Some(Arc::new(callback)));
+    }
+
+    /// Set the context of the futures spawned hereafter
+    /// (including via `SpawnHandle`s), replacing any
+    /// previous one. Within these futures, the context is
+    /// obtained via [`current_context`][crate::current_context],
+    /// say to share a trace id without capturing it in each
+    /// future.
+    pub fn set_context<C: Clone + Send + Sync + 'static>(&mut self, context: C) {
+        self.state.set_context(Some(Arc::new(context)));
+    }
+
+    /// The drop policy; see `with_drop_policy`.
+    #[inline]
+    pub fn drop_policy(&self) -> DropPolicy { self.drop_policy }
+
+    /// The fairness; see `with_fairness`.
+    #[inline]
+    pub fn fairness(&self) -> Fairness { self.fairness }
+
+    /// The concurrency limit, if any; see
+    /// `with_concurrency_limit`.
+    #[inline]
+    pub fn concurrency_limit(&self) -> Option<usize> { self.concurrency_limit }
+
+    /// The rate limit, if any; see `with_rate_limit`.
+    #[inline]
+    pub fn rate_limit(&self) -> Option<f64> {
+        self.rate_limit.as_ref().map(TokenBucket::per_second)
+    }
+
+    /// Number of futures still being driven by the
+    /// executor. Unlike `remaining`, this excludes futures
+    /// that have completed, but whose output has not been
+    /// yielded yet.
+    #[inline]
+    pub fn in_flight(&self) -> usize { self.state.limiter.in_flight() }
+
+    /// Total weight of the futures still being driven by
+    /// the executor; see `spawn_weighted`. Futures spawned
+    /// otherwise weigh one.
+    #[inline]
+    pub fn remaining_weight(&self) -> usize { self.state.limiter.weight() }
+
+    /// The spawner used to drive the futures of this scope.
     #[inline]
-    pub fn len(&self) -> usize { self.len }
+    pub fn spawner(&self) -> &Sp { &self.spawner }
 
-    /// Number of futures remaining in this scope.
+    /// The executor of this scope, to spawn `'static` work
+    /// onto it that is not scoped, say background
+    /// maintenance; see `ExecutorRef`.
     #[inline]
-    pub fn remaining(&self) -> usize { self.remaining }
+    pub fn executor(&self) -> ExecutorRef<'_, Sp> { ExecutorRef::new(&self.spawner) }
+
+    /// Pause the scope: `spawn_limited` (and the other
+    /// limited spawns) wait until the scope is resumed,
+    /// whether or not a concurrency limit is set. The
+    /// futures already spawned, and the stream, are not
+    /// affected; nor are the other spawns. The scope may be
+    /// resumed via `SpawnHandle::resume`, say, from a
+    /// spawned future monitoring some dependency.
+    #[inline]
+    pub fn pause(&self) { self.state.limiter.pause() }
+
+    /// Resume the scope, waking all the limited spawns (and
+    /// reservations) waiting on it; see `pause`.
+    #[inline]
+    pub fn resume(&self) { self.state.limiter.resume() }
+
+    /// Whether the scope is paused; see `pause`.
+    #[inline]
+    pub fn is_paused(&self) -> bool { self.state.limiter.is_paused() }
+
+    /// Total number of futures spawned in this scope; same
+    /// as `total_spawned`. Note that `is_empty` refers to
+    /// the futures remaining instead.
+    #[inline]
+    pub fn len(&self) -> usize { self.total_spawned() }
+
+    /// Total number of futures spawned in this scope,
+    /// including those spawned via a `SpawnHandle`. This
+    /// never decreases.
+    #[inline]
+    pub fn total_spawned(&self) -> usize { self.state.total_spawned() }
+
+    /// Whether no futures remain in this scope: see
+    /// `remaining`.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.remaining() == 0 }
+
+    /// Whether the outputs are yielded in the order of
+    /// spawning; see `with_ordering`.
+    #[inline]
+    pub fn is_ordered(&self) -> bool { self.futs.is_ordered() }
+
+    /// Number of futures remaining in this scope: every
+    /// future that has been spawned (whether or not it has
+    /// been polled, or has completed), and whose output has
+    /// not yet been yielded by the stream. This includes
+    /// futures whose output may be delivered to their
+    /// `ScopeHandle` instead; these are accounted for once
+    /// the stream reaches them.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.remaining
+            + self.deferred.lock().unwrap_or_else(PoisonError::into_inner).len()
+            + self.ready.len()
+            + self.state.orphans.len()
+            + self.state.incoming_len()
+    }
+
+    /// Number of futures remaining (see `remaining`) that
+    /// were spawned cancellable, and have neither completed
+    /// nor been cancelled yet: cancelling the scope would
+    /// complete these.
+    #[inline]
+    pub fn remaining_cancellable(&self) -> usize {
+        self.state.live_cancellable().min(self.remaining())
+    }
+
+    /// Number of futures remaining (see `remaining`) that
+    /// cancelling the scope would not complete: those that
+    /// were not spawned cancellable, and also those whose
+    /// output is yet to be yielded by the stream. With
+    /// `remaining_cancellable`, this adds up to `remaining`.
+    #[inline]
+    pub fn remaining_uncancellable(&self) -> usize {
+        self.remaining() - self.remaining_cancellable()
+    }
 
     /// A slighly optimized `collect` on the stream. Also
     /// useful when we can not move out of self.
     pub async fn collect(&mut self) -> Vec<T> {
+        let mut proc_outputs = Vec::new();
+        self.collect_into(&mut proc_outputs).await;
+        proc_outputs
+    }
+
+    /// Similar to `collect`, but appends the outputs to
+    /// `outputs`, say to reuse its allocation.
+    pub async fn collect_into(&mut self, outputs: &mut Vec<T>) {
+        outputs.reserve(self.remaining);
+        while let Some(item) = self.join_next().await {
+            outputs.push(item);
+        }
+    }
+
+    /// Drive all the spawned futures to completion,
+    /// discarding their outputs. Panics are resumed as in
+    /// the stream. Unlike an `impl Future` on the scope,
+    /// this only borrows it: forgetting the returned future
+    /// leaves the scope to be driven when it is dropped.
+    pub async fn drain(&mut self) {
+        while self.join_next().await.is_some() {}
+    }
+
+    /// Number of spawned futures that have completed so far
+    /// (including those that panicked, if panics are
+    /// caught), whether or not their outputs have been
+    /// yielded. As this only takes `&self`, it may be read
+    /// while the scope is shared, say for monitoring.
+    #[inline]
+    pub fn completed_count(&self) -> usize { self.state.completed() }
+
+    /// The outputs that are ready, without yielding them
+    /// from the stream: the outputs of the futures that have
+    /// completed are moved into a buffer of the scope
+    /// (without waiting for the others), which the stream
+    /// yields first, in the same order. The buffer stops at
+    /// a panicking or aborted future, which is yielded by
+    /// the stream once the buffer is exhausted.
+    ///
+    /// This takes `&mut self`, and hence can not race with
+    /// the stream being polled: the returned slice is a
+    /// snapshot, and may be cloned, say for monitoring.
+    pub fn peek_ready(&mut self) -> &[T] {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        while self.ready.accepts() {
+            match Pin::new(&mut *self).poll_next_tracked(&mut cx) {
+                Poll::Ready(Some((index, output))) => self.ready.push(index, output),
+                _ => break,
+            }
+        }
+        self.ready.outputs()
+    }
+
+    /// Similar to `drain`, but gives up once `timeout`
+    /// elapses. Returns whether all the spawned futures are
+    /// done. The futures that remain keep running, and are
+    /// still tracked (and borrowed) by the scope: they may
+    /// be drained again, or cancelled via `abort_all`, and
+    /// dropping the scope still waits for them.
+    pub async fn drain_timeout(&mut self, timeout: Duration) -> bool {
+        let mut deadline = futures_timer::Delay::new(timeout);
+        futures::future::poll_fn(|cx| loop {
+            match Pin::new(&mut *self).poll_next(cx) {
+                Poll::Ready(Some(_)) => {},
+                Poll::Ready(None) => return Poll::Ready(true),
+                Poll::Pending => return Pin::new(&mut deadline).poll(cx).map(|_| false),
+            }
+        }).await
+    }
+
+    /// The next output of the stream, or `None` once all
+    /// the spawned futures are done. This is equivalent to
+    /// `StreamExt::next`, without having to import it.
+    pub async fn join_next(&mut self) -> Option<T> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Drive the scope until fewer than `limit` futures
+    /// remain (see `remaining`), and return the outputs
+    /// yielded meanwhile, say to apply backpressure before
+    /// spawning more. Returns immediately if there is
+    /// capacity already, or once the scope is done.
+    pub async fn capacity(&mut self, limit: usize) -> Vec<T> {
+        let mut outputs = Vec::new();
+        while self.remaining() >= limit {
+            match self.join_next().await {
+                Some(output) => outputs.push(output),
+                None => break,
+            }
+        }
+        outputs
+    }
+
+    /// Append the outputs of the futures that have already
+    /// completed to `outputs`, without waiting for the
+    /// others, and return how many were appended. This
+    /// never blocks, and returns 0 if no output is ready
+    /// yet. Panics are resumed as in the stream.
+    pub fn drain_ready(&mut self, outputs: &mut Vec<T>) -> usize {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let len = outputs.len();
+        while let Poll::Ready(Some(output)) = Pin::new(&mut *self).poll_next(&mut cx) {
+            outputs.push(output);
+        }
+        outputs.len() - len
+    }
+
+    /// Wait for the next output, then collect it along with
+    /// every other output that is ready by then (as in
+    /// `drain_ready`), without waiting any further. Returns
+    /// an empty `Vec` once all the spawned futures are
+    /// done. The scope may be spawned into, and awaited,
+    /// again afterwards, say to flush the outputs
+    /// periodically.
+    pub async fn collect_available(&mut self) -> Vec<T> {
+        let mut outputs = Vec::new();
+        if let Some(output) = self.join_next().await {
+            outputs.push(output);
+            self.drain_ready(&mut outputs);
+        }
+        outputs
+    }
+
+    /// Shut down the scope in two phases: the futures are
+    /// first driven until `timeout` elapses, after which
+    /// the pending cancellable futures are cancelled (as in
+    /// `cancel`). Futures that can not be cancelled are
+    /// still waited for, and are reported as overdue in the
+    /// returned summary.
+    ///
+    /// Note that cancellable futures spawned after the
+    /// deadline are cancelled immediately.
+    pub async fn graceful_shutdown(&mut self, timeout: Duration) -> ShutdownSummary<T> {
+        let finished = |scope: &Self| scope.len - scope.remaining;
+        let start = finished(self);
+        let mut outputs = Vec::with_capacity(self.remaining);
+
+        let timed_out = self.collect_until(timeout, &mut outputs).await;
+        let completed = finished(self) - start;
+        if !timed_out {
+            return ShutdownSummary { outputs, completed, cancelled: 0, overdue: 0 };
+        }
+
+        let cancelled_before = self.state.cancelled();
+        let at_deadline = finished(self);
+        self.cancel_with(CancelReason::Timeout).await;
+        outputs.extend(self.collect_caught().await);
+
+        let cancelled = self.state.cancelled() - cancelled_before;
+        let overdue = finished(self) - at_deadline - cancelled;
+        ShutdownSummary { outputs, completed, cancelled, overdue }
+    }
+
+    /// Collect the outputs that arrive within `timeout`.
+    /// The pending cancellable futures are then cancelled
+    /// (as in `cancel`), and the remaining futures driven
+    /// to completion, discarding their outputs. Thus, the
+    /// futures are no longer borrowed once this returns,
+    /// and dropping the returned future midway blocks as
+    /// usual. The futures that could not be cancelled are
+    /// reported as still running at the deadline.
+    pub async fn collect_timeout(&mut self, timeout: Duration) -> PartialOutputs<T> {
+        let mut outputs = Vec::with_capacity(self.remaining);
+        let timed_out = self.collect_until(timeout, &mut outputs).await;
+
+        let mut still_running = 0;
+        if timed_out {
+            let finished = |scope: &Self| scope.len - scope.remaining;
+            let cancelled_before = self.state.cancelled();
+            let at_deadline = finished(self);
+            self.cancel_with(CancelReason::Timeout).await;
+            self.collect_caught().await;
+            let cancelled = self.state.cancelled() - cancelled_before;
+            still_running = finished(self) - at_deadline - cancelled;
+        }
+
+        let outputs = outputs.into_iter().map(|output| match output {
+            Ok(output) => output,
+            Err(caught) => std::panic::resume_unwind(caught.into_payload()),
+        }).collect();
+        PartialOutputs { outputs, timed_out, still_running }
+    }
+
+    /// Collect the (caught) outputs into `outputs` until
+    /// all the futures are done, or `timeout` elapses.
+    /// Returns whether it elapsed.
+    async fn collect_until(&mut self, timeout: Duration,
+                           outputs: &mut Vec<Result<T, CaughtPanic>>) -> bool {
+        let mut deadline = futures_timer::Delay::new(timeout);
+        futures::future::poll_fn(|cx| loop {
+            match Pin::new(&mut *self).poll_next_caught(cx) {
+                Poll::Ready(Some(output)) => outputs.push(output),
+                Poll::Ready(None) => return Poll::Ready(false),
+                Poll::Pending => return Pin::new(&mut deadline).poll(cx).map(|_| true),
+            }
+        }).await
+    }
+
+    /// The stream of progress events reported by the
+    /// futures spawned via `spawn_with_progress`. The
+    /// stream is independent of the scope (and may be
+    /// polled alongside it), and ends once the scope (and
+    /// its `SpawnHandle`s) are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    pub fn progress_events(&self) -> ProgressEvents {
+        let rx = self.state.take_progress_rx()
+            .expect("progress events already taken");
+        ProgressEvents::new(rx)
+    }
+
+    /// Convert into a stream of the outputs, which can no
+    /// longer spawn futures (save via the handles obtained
+    /// before). The stream retains the lifetime 'a of the
+    /// scope, and drives it to completion when dropped.
+    #[inline]
+    pub fn into_stream(self) -> ScopeStream<'a, T, Sp> {
+        ScopeStream { scope: self }
+    }
+
+    /// A stream of the outputs of the spawned futures,
+    /// where a caught panic is yielded as an `Err` instead
+    /// of being resumed.
+    #[inline]
+    pub fn caught(&mut self) -> Caught<'_, 'a, T, Sp> {
+        Caught { scope: self }
+    }
+
+    /// A stream of batches of the outputs, as in
+    /// `StreamExt::ready_chunks`: each batch holds the
+    /// outputs that are ready (up to `max` of them), and is
+    /// yielded without waiting for more, say to amortize
+    /// the cost of their processing. Panics are resumed as
+    /// in the scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    #[inline]
+    pub fn chunks_ready(&mut self, max: usize) -> ReadyChunks<&mut Self> {
+        futures::StreamExt::ready_chunks(self, max)
+    }
+
+    /// Similar to `collect`, but caught panics are
+    /// collected as an `Err` instead of being resumed.
+    pub async fn collect_caught(&mut self) -> Vec<Result<T, CaughtPanic>> {
         let mut proc_outputs = Vec::with_capacity(self.remaining);
 
         use futures::StreamExt;
-        while let Some(item) = self.next().await {
+        let mut stream = self.caught();
+        while let Some(item) = stream.next().await {
             proc_outputs.push(item);
         }
 
         proc_outputs
     }
+
+    /// Poll for the next output, as in `Stream::poll_next`.
+    /// This is provided as an inherent method to ease
+    /// polling the scope from a hand-written `Future` or
+    /// `Stream`, without importing the trait. As the scope
+    /// is `Unpin`, it may be pinned via `Pin::new`.
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
+                     -> Poll<Option<T>> {
+        self.poll_next_caught(cx).map(|item| item.map(|output| match output {
+            Ok(output) => output,
+            Err(caught) => std::panic::resume_unwind(caught.into_payload()),
+        }))
+    }
+
+    pub(crate) fn poll_next_caught(self: Pin<&mut Self>, cx: &mut Context)
+                                   -> Poll<Option<Result<T, CaughtPanic>>> {
+        self.poll_next_indexed(cx).map(|item| item.map(|(_, output)| output))
+    }
+
+    /// Poll for the next output, along with the index (in
+    /// order of spawning) of its future.
+    pub(crate) fn poll_next_indexed(mut self: Pin<&mut Self>, cx: &mut Context)
+                                    -> Poll<Option<(usize, Result<T, CaughtPanic>)>> {
+        loop {
+            return match self.as_mut().poll_next_joined(cx) {
+                Poll::Ready(Some((index, output))) => match output {
+                    Ok(output) => Poll::Ready(Some((index, Ok(output)))),
+                    Err(JoinError::Panic(caught)) => Poll::Ready(Some((index, Err(caught)))),
+                    // Aborted futures are skipped
+                    Err(JoinError::Cancelled { .. }) => continue,
+                },
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+
+    /// Poll for the next output, or the aborted future,
+    /// along with the index of its future.
+    pub(crate) fn poll_next_joined(mut self: Pin<&mut Self>, cx: &mut Context)
+                                   -> Poll<Option<(usize, Result<T, JoinError>)>> {
+        let this = self.as_mut().project();
+        if let Some(budget) = *this.coop_budget {
+            if *this.coop_yielded >= budget {
+                *this.coop_yielded = 0;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        }
+
+        // Outputs taken by `peek_ready` come first
+        let item = match self.ready.pop() {
+            Some(item) => Poll::Ready(Some(item)),
+            None => self.as_mut().poll_next_tracked(cx),
+        };
+        let this = self.project();
+        match item {
+            Poll::Ready(Some(_)) => *this.coop_yielded += 1,
+            _ => *this.coop_yielded = 0,
+        }
+        item
+    }
+
+    /// Similar to `poll_next_joined`, without the coop
+    /// budget, and the outputs taken by `peek_ready`.
+    fn poll_next_tracked(self: Pin<&mut Self>, cx: &mut Context)
+                         -> Poll<Option<(usize, Result<T, JoinError>)>> {
+
+        let mut this = self.project();
+        loop {
+            // Track the futures spawned via handles
+            let incoming = this.state.poll_incoming(cx);
+            if !incoming.is_empty() {
+                *this.done = false;
+                *this.len += incoming.len();
+                *this.remaining += incoming.len();
+                for join in incoming {
+                    this.futs.as_mut().get_mut().push(join);
+                }
+            }
+
+            // Spawn the futures held back, as the window moves
+            if let Some(window) = this.futs.window() {
+                let deferred = this.deferred.get_mut().unwrap_or_else(PoisonError::into_inner);
+                while this.futs.len() < window {
+                    match deferred.pop_front() {
+                        Some(spawn) => {
+                            *this.len += 1;
+                            *this.remaining += 1;
+                            let join = spawn(this.spawner, this.thread_affinity.as_deref());
+                            this.futs.as_mut().get_mut().push(join);
+                        },
+                        None => break,
+                    }
+                }
+            }
+
+            if let Some((index, output)) = this.state.orphans.poll_pop(cx) {
+                return Poll::Ready(Some((index, output.map_err(JoinError::Panic))));
+            }
+            match this.futs.as_mut().poll_next(cx) {
+                Poll::Ready(Some(completion)) => {
+                    *this.remaining -= 1;
+                    // Outputs sent to the handle are skipped
+                    match completion.into_output() {
+                        Some(Ok((index, output))) => {
+                            return Poll::Ready(Some((index, output.map_err(JoinError::Panic))));
+                        },
+                        Some(Err(index)) => {
+                            return Poll::Ready(Some((index, Err(JoinError::Cancelled { index }))));
+                        },
+                        None => {},
+                    }
+                },
+                Poll::Ready(None) => {
+                    // Release the undrained tagged outputs
+                    if !this.tagged.is_empty() {
+                        this.tagged.clear();
+                        continue;
+                    }
+                    *this.done = true;
+                    return Poll::Ready(None);
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+    }
 }
 
-impl<'a, T> Stream for Scope<'a, T> {
+impl<'a, T, Sp: Spawner> Stream for Scope<'a, T, Sp> {
     type Item = T;
 
+    #[inline]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
                  -> Poll<Option<Self::Item>> {
+        Scope::poll_next(self, cx)
+    }
 
-        let this = self.project();
-        let poll = this.futs.poll_next(cx);
-        if let Poll::Ready(None) = poll {
-            *this.done = true;
-        } else if poll.is_ready() {
-            *this.remaining -= 1;
+    /// The lower bound is the number of pending outputs,
+    /// including those of futures that may yet deliver
+    /// them to their `ScopeHandle`. There is no upper bound
+    /// while futures may be spawned via a `SpawnHandle`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.remaining();
+        if Arc::strong_count(&self.state) > 1 {
+            (pending, None)
+        } else {
+            (pending, Some(pending))
         }
-        poll
-
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.remaining, Some(self.remaining))
+impl<'a, T, Sp: Spawner> FusedStream for Scope<'a, T, Sp> {
+    /// Whether all the outputs have been yielded, and no
+    /// futures may be spawned via a `SpawnHandle`. This is
+    /// reset if more futures are spawned in the scope.
+    fn is_terminated(&self) -> bool {
+        self.size_hint() == (0, Some(0))
     }
 }
 
+/// Spawns a wrapped future held back by the window of
+/// `with_ordered_window`, onto one of the workers if given.
+type Deferred<'a, T, Sp> = Box<dyn FnOnce(&Sp, Option<&[usize]>)
+                                          -> <Sp as Spawner>::Handle<Completion<T>> + Send + 'a>;
+
+/// Yield to the executor once, waking the current task.
+async fn yield_now() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }).await
+}
+
 #[pinned_drop]
-impl<'a, T> PinnedDrop for Scope<'a, T> {
+impl<'a, T, Sp: Spawner> PinnedDrop for Scope<'a, T, Sp> {
     fn drop(mut self: Pin<&mut Self>) {
-        if !self.done {
-            async_std::task::block_on(async {
-                self.cancel().await;
-                self.collect().await;
-            });
+        if self.done && self.state.try_close() {
+            return;
+        }
+        Sp::block_on(async {
+            if self.drop_policy != DropPolicy::BlockOnFinish {
+                self.cancel_with(CancelReason::ScopeDropped).await;
+            }
+            // Futures may be spawned via handles meanwhile
+            loop {
+                if self.drop_policy == DropPolicy::AbortAll {
+                    self.abort_pending();
+                }
+                self.collect_caught().await;
+                if self.state.try_close() { break; }
+            }
+        });
+    }
+}
+
+/// A stream of the outputs of the futures spawned in a
+/// `Scope`, where panics are yielded as an `Err`. Created
+/// by `Scope::caught`.
+pub struct Caught<'s, 'a, T,
+                  #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                  #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: &'s mut Scope<'a, T, Sp>,
+}
+
+impl<'s, 'a, T, Sp: Spawner> Stream for Caught<'s, 'a, T, Sp> {
+    type Item = Result<T, CaughtPanic>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        Pin::new(&mut *self.scope).poll_next_caught(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'s, 'a, T, Sp: Spawner> FusedStream for Caught<'s, 'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
+    }
+}
+
+/// A `Scope` that yields the outputs of its futures along
+/// with their index, in order of spawning (starting from
+/// zero). The index does not depend on the order of
+/// completion. Created by `Scope::with_indexing`; futures
+/// are spawned via the (dereferenced) scope.
+pub struct Indexed<'a, T,
+                   #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                   #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: Scope<'a, T, Sp>,
+    assert_ordered: bool,
+    next_index: usize,
+}
+
+impl<'a, T, Sp: Spawner> Indexed<'a, T, Sp> {
+    /// The underlying scope, yielding plain outputs.
+    #[inline]
+    pub fn into_inner(self) -> Scope<'a, T, Sp> { self.scope }
+
+    /// Check that the outputs are yielded in order of
+    /// spawning, say via `with_ordering`, panicking
+    /// otherwise. This catches accidental reliance on the
+    /// order of the outputs in tests. The check is only
+    /// made in debug builds: in release builds, this does
+    /// nothing. Aborted futures, which yield no output, are
+    /// skipped in the order.
+    #[inline]
+    pub fn with_assert_ordered(mut self, assert: bool) -> Self {
+        self.assert_ordered = assert;
+        self
+    }
+}
+
+impl<'a, T, Sp: Spawner> Deref for Indexed<'a, T, Sp> {
+    type Target = Scope<'a, T, Sp>;
+
+    fn deref(&self) -> &Self::Target { &self.scope }
+}
+
+impl<'a, T, Sp: Spawner> DerefMut for Indexed<'a, T, Sp> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.scope }
+}
+
+impl<'a, T, Sp: Spawner> Stream for Indexed<'a, T, Sp> {
+    type Item = (usize, T);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        let item = Pin::new(&mut self.scope).poll_next_indexed(cx);
+        if cfg!(debug_assertions) && self.assert_ordered {
+            if let Poll::Ready(Some((index, _))) = &item {
+                assert!(*index >= self.next_index,
+                        "output of future {} yielded after that of future {}",
+                        index, self.next_index - 1);
+                self.next_index = index + 1;
+            }
         }
+        item.map(|item| item.map(|(index, output)| match output {
+            Ok(output) => (index, output),
+            Err(caught) => std::panic::resume_unwind(caught.into_payload()),
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'a, T, Sp: Spawner> FusedStream for Indexed<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
+    }
+}
+
+/// A `Scope` that yields `Ok` for the output of each
+/// future, and `Err` for the futures that panicked (even if
+/// panics are not resumed) or were aborted. Similar to
+/// `tokio::task::JoinSet::join_next`. Created by
+/// `Scope::with_join_errors`; futures are spawned via the
+/// (dereferenced) scope.
+pub struct Joined<'a, T,
+                  #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                  #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: Scope<'a, T, Sp>,
+}
+
+impl<'a, T, Sp: Spawner> Joined<'a, T, Sp> {
+    /// The underlying scope, yielding plain outputs.
+    #[inline]
+    pub fn into_inner(self) -> Scope<'a, T, Sp> { self.scope }
+
+    /// The next result, or `None` once all the spawned
+    /// futures are done.
+    pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        use futures::StreamExt;
+        self.next().await
+    }
+}
+
+impl<'a, T, Sp: Spawner> Deref for Joined<'a, T, Sp> {
+    type Target = Scope<'a, T, Sp>;
+
+    fn deref(&self) -> &Self::Target { &self.scope }
+}
+
+impl<'a, T, Sp: Spawner> DerefMut for Joined<'a, T, Sp> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.scope }
+}
+
+impl<'a, T, Sp: Spawner> Stream for Joined<'a, T, Sp> {
+    type Item = Result<T, JoinError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        let item = Pin::new(&mut self.scope).poll_next_joined(cx);
+        item.map(|item| item.map(|(_, output)| output))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'a, T, Sp: Spawner> FusedStream for Joined<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
+    }
+}
+
+/// A `Scope` that yields the outputs of its futures along
+/// with the time each future took to complete, say for
+/// benchmarks. Created by `Scope::with_timing`; futures are
+/// spawned via the (dereferenced) scope. The futures
+/// spawned before the timing was enabled are yielded with
+/// a zero duration.
+pub struct Timed<'a, T,
+                 #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                 #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: Scope<'a, T, Sp>,
+}
+
+impl<'a, T, Sp: Spawner> Timed<'a, T, Sp> {
+    /// The underlying scope, yielding plain outputs.
+    #[inline]
+    pub fn into_inner(self) -> Scope<'a, T, Sp> { self.scope }
+}
+
+impl<'a, T, Sp: Spawner> Deref for Timed<'a, T, Sp> {
+    type Target = Scope<'a, T, Sp>;
+
+    fn deref(&self) -> &Self::Target { &self.scope }
+}
+
+impl<'a, T, Sp: Spawner> DerefMut for Timed<'a, T, Sp> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.scope }
+}
+
+impl<'a, T, Sp: Spawner> Stream for Timed<'a, T, Sp> {
+    type Item = (Duration, T);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        let item = Pin::new(&mut self.scope).poll_next_indexed(cx);
+        item.map(|item| item.map(|(index, output)| match output {
+            Ok(output) => {
+                let elapsed = self.scope.state.take_timing(index).unwrap_or_default();
+                (elapsed, output)
+            },
+            Err(caught) => std::panic::resume_unwind(caught.into_payload()),
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'a, T, Sp: Spawner> FusedStream for Timed<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
+    }
+}
+
+/// The consuming side of a `Scope`: a stream of the outputs
+/// of its futures, with combinators to collect them.
+/// Created by `Scope::into_stream`.
+pub struct ScopeStream<'a, T,
+                       #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                       #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: Scope<'a, T, Sp>,
+}
+
+impl<'a, T, Sp: Spawner> ScopeStream<'a, T, Sp> {
+    /// The underlying scope.
+    #[inline]
+    pub fn into_inner(self) -> Scope<'a, T, Sp> { self.scope }
+
+    /// Number of outputs yet to be yielded; see
+    /// `Scope::remaining`.
+    #[inline]
+    pub fn remaining(&self) -> usize { self.scope.remaining() }
+
+    /// Collect the outputs into a `Vec`, in order of
+    /// completion; see `Scope::collect`.
+    #[inline]
+    pub async fn collect(&mut self) -> Vec<T> {
+        self.scope.collect().await
+    }
+
+    /// Collect the outputs into batches of (at most) `size`
+    /// outputs each, in order of completion. Only the last
+    /// batch may hold fewer than `size` outputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub async fn buffered_collect(&mut self, size: usize) -> Vec<Vec<T>> {
+        assert!(size > 0, "batch size must be positive");
+        let mut batches = vec![];
+        let mut batch = Vec::with_capacity(size.min(self.remaining()));
+        while let Some(output) = self.scope.join_next().await {
+            batch.push(output);
+            if batch.len() == size {
+                let capacity = size.min(self.remaining());
+                batches.push(std::mem::replace(&mut batch, Vec::with_capacity(capacity)));
+            }
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    /// Collect the outputs into a `Vec`, in the order the
+    /// futures were spawned. Unlike `Scope::with_ordering`,
+    /// the futures are driven as usual, and the outputs are
+    /// sorted once all of them are done. The futures whose
+    /// output was awaited via their `ScopeHandle` are
+    /// skipped.
+    pub async fn collect_ordered(&mut self) -> Vec<T> {
+        let mut outputs = Vec::with_capacity(self.remaining());
+        let scope = &mut self.scope;
+        futures::future::poll_fn(|cx| loop {
+            match Pin::new(&mut *scope).poll_next_indexed(cx) {
+                Poll::Ready(Some((index, Ok(output)))) => outputs.push((index, output)),
+                Poll::Ready(Some((_, Err(caught)))) => {
+                    std::panic::resume_unwind(caught.into_payload())
+                },
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }).await;
+        outputs.sort_by_key(|(index, _)| *index);
+        outputs.into_iter().map(|(_, output)| output).collect()
+    }
+}
+
+impl<'a, T, Sp: Spawner> Stream for ScopeStream<'a, T, Sp> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.scope).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'a, T, Sp: Spawner> FusedStream for ScopeStream<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
+    }
+}
+
+/// A `Scope` that yields a `TaskOutcome` for each future:
+/// `Cancelled` with the default value of a cancellable
+/// future that was cancelled, and `Completed` with the
+/// output otherwise. Created by
+/// `Scope::with_outcome_tagging`; futures are spawned via
+/// `spawn` and `spawn_cancellable`, which tag their
+/// outputs.
+pub struct Tagged<'a, T,
+                  #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                  #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    scope: Scope<'a, TaskOutcome<T>, Sp>,
+}
+
+impl<'a, T, Sp: Spawner> Tagged<'a, T, Sp> {
+    /// The underlying scope, yielding the tagged outputs.
+    #[inline]
+    pub fn into_inner(self) -> Scope<'a, TaskOutcome<T>, Sp> { self.scope }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> Tagged<'a, T, Sp> {
+    /// Spawn a future, whose output is yielded as
+    /// `Completed`; see `Scope::spawn`.
+    pub fn spawn<F: Future<Output=T> + Send + 'a>(&mut self, f: F)
+                                                 -> ScopeHandle<'a, TaskOutcome<T>> {
+        self.scope.spawn(async move { TaskOutcome::Completed(f.await) })
+    }
+
+    /// Spawn a cancellable future, whose output is yielded
+    /// as `Completed`, or its default value as `Cancelled`
+    /// if it is cancelled; see `Scope::spawn_cancellable`.
+    pub fn spawn_cancellable<F: Future<Output=T> + Send + 'a,
+                             Fu: FnOnce() -> T + Send + 'a>(
+        &mut self, f: F, default: Fu
+    ) -> ScopeHandle<'a, TaskOutcome<T>> {
+        self.scope.spawn_cancellable(async move { TaskOutcome::Completed(f.await) },
+                                     move || TaskOutcome::Cancelled(default()))
+    }
+}
+
+impl<'a, T, Sp: Spawner> Deref for Tagged<'a, T, Sp> {
+    type Target = Scope<'a, TaskOutcome<T>, Sp>;
+
+    fn deref(&self) -> &Self::Target { &self.scope }
+}
+
+impl<'a, T, Sp: Spawner> DerefMut for Tagged<'a, T, Sp> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.scope }
+}
+
+impl<'a, T, Sp: Spawner> Stream for Tagged<'a, T, Sp> {
+    type Item = TaskOutcome<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.scope).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scope.size_hint()
+    }
+}
+
+impl<'a, T, Sp: Spawner> FusedStream for Tagged<'a, T, Sp> {
+    fn is_terminated(&self) -> bool {
+        self.scope.is_terminated()
     }
 }
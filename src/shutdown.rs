@@ -0,0 +1,48 @@
+use crate::CaughtPanic;
+
+/// Summary of a `Scope::graceful_shutdown`, accounting for
+/// every future that was pending when the shutdown began.
+#[derive(Debug)]
+pub struct ShutdownSummary<T> {
+    /// Outputs yielded by the scope during the shutdown.
+    /// This excludes outputs delivered to a `ScopeHandle`.
+    pub outputs: Vec<Result<T, CaughtPanic>>,
+
+    /// Number of futures that completed before the
+    /// deadline.
+    pub completed: usize,
+
+    /// Number of cancellable futures that were cancelled
+    /// after the deadline.
+    pub cancelled: usize,
+
+    /// Number of futures that could not be cancelled, and
+    /// completed after the deadline. The shutdown waits for
+    /// these to complete.
+    pub overdue: usize,
+}
+
+impl<T> ShutdownSummary<T> {
+    /// Whether every future completed before the deadline.
+    #[inline]
+    pub fn is_clean(&self) -> bool {
+        self.cancelled == 0 && self.overdue == 0
+    }
+}
+
+/// The outputs collected by `Scope::collect_timeout`, or
+/// `scope_and_collect_timeout`, within the timeout.
+#[derive(Debug)]
+pub struct PartialOutputs<T> {
+    /// Outputs yielded by the scope before the deadline.
+    pub outputs: Vec<T>,
+
+    /// Whether the deadline elapsed before all the futures
+    /// completed.
+    pub timed_out: bool,
+
+    /// Number of futures that could not be cancelled, and
+    /// were still running at the deadline. These were
+    /// waited for, and their outputs discarded.
+    pub still_running: usize,
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{Spawner, ScopeHandle};
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+use crate::DefaultSpawner;
+use crate::handle::Completion;
+use crate::state::{self, State};
+
+/// A handle to spawn futures into a `Scope`, obtained via
+/// `Scope::handle`. Unlike the scope, the handle may be
+/// cloned and moved into the spawned futures, say, to spawn
+/// recursively. The scope is driven to completion only once
+/// all the futures spawned via its handles complete.
+///
+/// The futures spawned via a handle do not respect the
+/// concurrency limit of the scope.
+///
+/// Each handle has a depth: the handle obtained from the
+/// scope has depth zero, and `spawn_nested` passes a handle
+/// of one more depth to the spawned future. The depth is
+/// bounded by `Scope::with_max_depth`, say to prevent a
+/// runaway recursion.
+pub struct SpawnHandle<'a, T,
+                       #[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))] Sp: Spawner = DefaultSpawner,
+                       #[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))] Sp: Spawner> {
+    state: Arc<State<T, Sp::Handle<Completion<T>>>>,
+    spawner: Sp,
+    depth: usize,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'a ()) -> &'a ()>
+}
+
+impl<'a, T, Sp: Spawner> SpawnHandle<'a, T, Sp> {
+    pub(crate) fn new(state: Arc<State<T, Sp::Handle<Completion<T>>>>,
+                      spawner: Sp) -> Self {
+        SpawnHandle { state, spawner, depth: 0, _marker: PhantomData }
+    }
+
+    /// The depth of this handle; see `spawn_nested`.
+    #[inline]
+    pub fn depth(&self) -> usize { self.depth }
+
+    /// Pause the scope; see `Scope::pause`.
+    #[inline]
+    pub fn pause(&self) { self.state.limiter.pause() }
+
+    /// Resume the scope; see `Scope::resume`.
+    #[inline]
+    pub fn resume(&self) { self.state.limiter.resume() }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner> SpawnHandle<'a, T, Sp> {
+    /// Spawn a future into the scope; see `Scope::spawn`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scope has been dropped.
+    pub fn spawn<F: Future<Output=T> + Send + 'a>(&self, f: F) -> ScopeHandle<'a, T> {
+        let (task, handle) = self.state.wrap(f, true);
+        let spawned = self.state.push_incoming(|| unsafe {
+            state::spawn_task(&self.spawner, task)
+        });
+        assert!(spawned, "spawned via the handle of a dropped scope");
+        handle
+    }
+
+    /// Spawn a cancellable future into the scope; see
+    /// `Scope::spawn_cancellable`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scope has been dropped.
+    #[inline]
+    pub fn spawn_cancellable<F: Future<Output=T> + Send + 'a,
+                             Fu: FnOnce() -> T + Send + 'a>(
+        &self, f: F, default: Fu
+    ) -> ScopeHandle<'a, T> {
+        self.spawn(crate::CancellableFuture::new(
+            self.state.cancellation().clone(), f, move |_| default()
+        ))
+    }
+}
+
+impl<'a, T: Send + 'static, Sp: Spawner + Clone> SpawnHandle<'a, T, Sp> {
+    /// Spawn the future `f(handle)` into the scope, where
+    /// `handle` is a handle of one more depth than this
+    /// one, to be used by the future to spawn recursively.
+    /// Fails if the depth would exceed the maximum depth of
+    /// the scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scope has been dropped.
+    pub fn spawn_nested<F, Fu>(&self, f: Fu) -> Result<ScopeHandle<'a, T>, DepthExceeded>
+    where F: Future<Output=T> + Send + 'a,
+          Fu: FnOnce(SpawnHandle<'a, T, Sp>) -> F
+    {
+        let depth = self.depth + 1;
+        if let Some(max_depth) = self.state.max_depth() {
+            if depth > max_depth {
+                return Err(DepthExceeded { max_depth });
+            }
+        }
+        let mut child = self.clone();
+        child.depth = depth;
+        Ok(self.spawn(f(child)))
+    }
+}
+
+impl<'a, T, Sp: Spawner + Clone> Clone for SpawnHandle<'a, T, Sp> {
+    fn clone(&self) -> Self {
+        SpawnHandle {
+            state: self.state.clone(),
+            spawner: self.spawner.clone(),
+            depth: self.depth,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// The error returned by `SpawnHandle::spawn_nested` when
+/// the maximum depth of the scope would be exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthExceeded {
+    /// The maximum depth of the scope.
+    pub max_depth: usize,
+}
+
+impl fmt::Display for DepthExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "maximum spawn depth of {} exceeded", self.max_depth)
+    }
+}
+
+impl std::error::Error for DepthExceeded {}
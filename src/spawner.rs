@@ -0,0 +1,505 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Poll, Context};
+
+use futures::task::ArcWake;
+
+/// An executor that drives the futures spawned in a
+/// `Scope`. Implementations are provided for async-std
+/// (`AsyncStdSpawner`), tokio (`TokioSpawner`) and smol
+/// (`SmolSpawner`) under the respective features; other executors may be supported
+/// by implementing this trait.
+///
+/// # Safety
+///
+/// The `Scope` relies on the handles returned by `spawn` to
+/// guarantee soundness. Implementations must ensure that
+/// the handle resolves only after the spawned future has
+/// been driven to completion, and that `block_on` does not
+/// return before the future passed to it resolves.
+///
+/// # Examples
+///
+/// A spawner that drives each future on a thread of its
+/// own, which needs none of the executor features:
+///
+/// ```
+/// use std::future::Future;
+/// use futures::future::BoxFuture;
+///
+/// struct ThreadSpawner;
+///
+/// unsafe impl async_scoped::Spawner for ThreadSpawner {
+///     type Handle<T> = BoxFuture<'static, T>;
+///
+///     fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+///     where F: Future + Send + 'static, F::Output: Send + 'static
+///     {
+///         let (tx, rx) = futures::channel::oneshot::channel();
+///         std::thread::spawn(move || {
+///             let _ = tx.send(futures::executor::block_on(f));
+///         });
+///         Box::pin(async move { rx.await.expect("spawned thread panicked") })
+///     }
+///
+///     fn block_on<F: Future>(f: F) -> F::Output {
+///         futures::executor::block_on(f)
+///     }
+/// }
+///
+/// let words = [String::from("scoped"), String::from("futures")];
+/// let ((), mut lens) = async_scoped::scope_and_block_on(ThreadSpawner, |s| {
+///     for word in &words {
+///         s.spawn(async move { word.len() });
+///     }
+/// });
+/// lens.sort();
+/// assert_eq!(lens, [6, 7]);
+/// ```
+pub unsafe trait Spawner {
+    /// Handle to a spawned future, resolving to its output.
+    type Handle<T>: Future<Output=T>;
+
+    /// Spawn a `'static` future onto the executor.
+    fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+    where F: Future + Send + 'static, F::Output: Send + 'static;
+
+    /// Run a blocking closure on a thread where blocking is
+    /// acceptable. The default implementation runs it as a
+    /// future via `spawn`.
+    fn spawn_blocking<F, T>(&self, f: F) -> Self::Handle<T>
+    where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+    {
+        self.spawn(async move { f() })
+    }
+
+    /// Spawn a `'static` future, named `name` where the
+    /// executor supports it, say for diagnostics. The
+    /// default implementation ignores the name, and spawns
+    /// via `spawn`.
+    fn spawn_named<F>(&self, name: &str, f: F) -> Self::Handle<F::Output>
+    where F: Future + Send + 'static, F::Output: Send + 'static
+    {
+        let _ = name;
+        self.spawn(f)
+    }
+
+    /// Spawn a `'static` future onto one of the worker
+    /// threads `workers` (as numbered by the executor),
+    /// where the executor supports pinning tasks to its
+    /// workers; see `Scope::with_thread_affinity`. The
+    /// default implementation ignores `workers`, and spawns
+    /// via `spawn`; none of the executors supported by this
+    /// crate can pin tasks, hence all of them use it.
+    fn spawn_on_workers<F>(&self, workers: &[usize], f: F) -> Self::Handle<F::Output>
+    where F: Future + Send + 'static, F::Output: Send + 'static
+    {
+        let _ = workers;
+        self.spawn(f)
+    }
+
+    /// Spawn a `'static` future that is not awaited, say
+    /// via `ExecutorRef::spawn_detached`. The default
+    /// implementation spawns via `spawn`, and drops the
+    /// handle; executors that cancel a task as its handle
+    /// is dropped must override this.
+    fn spawn_detached<F>(&self, f: F)
+    where F: Future<Output=()> + Send + 'static
+    {
+        std::mem::drop(self.spawn(f));
+    }
+
+    /// Block the current thread until `f` resolves. Used to
+    /// drive a `Scope` to completion when it is dropped.
+    fn block_on<F: Future>(f: F) -> F::Output;
+}
+
+/// The executor of a `Scope`, obtained via
+/// `Scope::executor`, to spawn work that is not scoped onto
+/// the same executor. The futures spawned via `spawn_detached`
+/// are `'static`, and are neither awaited nor cancelled by
+/// the scope.
+pub struct ExecutorRef<'s, Sp> {
+    spawner: &'s Sp,
+}
+
+impl<'s, Sp: Spawner> ExecutorRef<'s, Sp> {
+    pub(crate) fn new(spawner: &'s Sp) -> Self {
+        ExecutorRef { spawner }
+    }
+
+    /// The name of the spawner type, say `TokioSpawner`,
+    /// for diagnostics.
+    pub fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Sp>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// Spawn `f` onto the executor, without awaiting it; see
+    /// `Spawner::spawn_detached`.
+    pub fn spawn_detached<F>(&self, f: F)
+    where F: Future<Output=()> + Send + 'static
+    {
+        self.spawner.spawn_detached(f)
+    }
+}
+
+impl<'s, Sp> Clone for ExecutorRef<'s, Sp> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<'s, Sp> Copy for ExecutorRef<'s, Sp> {}
+
+impl<'s, Sp: Spawner> std::fmt::Debug for ExecutorRef<'s, Sp> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("ExecutorRef").field(&self.name()).finish()
+    }
+}
+
+/// An executor that can drive `!Send` futures on the
+/// current thread, as spawned in a `LocalScope`.
+///
+/// # Safety
+///
+/// The same requirements as `Spawner` apply to the handles
+/// returned by `spawn_local`.
+pub unsafe trait LocalSpawner: Spawner {
+    /// Spawn a `'static` future onto the executor of the
+    /// current thread.
+    fn spawn_local<F>(&self, f: F) -> Self::Handle<F::Output>
+    where F: Future + 'static, F::Output: 'static;
+}
+
+/// Block the current thread until `f` resolves, without
+/// entering any executor: unlike `futures::executor::block_on`,
+/// this may be called from within another executor (which
+/// is then blocked meanwhile).
+pub(crate) fn park_on<F: Future>(f: F) -> F::Output {
+    struct Unparker(std::thread::Thread);
+
+    impl ArcWake for Unparker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.unpark();
+        }
+    }
+
+    let waker = futures::task::waker(Arc::new(Unparker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    futures::pin_mut!(f);
+    loop {
+        if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// The spawner used by `Scope::create` and the `scope*`
+/// functions: `TokioSpawner` if the `tokio` feature is
+/// enabled, else `SmolSpawner` if the `smol` feature is
+/// enabled, and `AsyncStdSpawner` otherwise.
+#[cfg(feature = "tokio")]
+pub type DefaultSpawner = TokioSpawner;
+
+/// The spawner used by `Scope::create` and the `scope*`
+/// functions: `TokioSpawner` if the `tokio` feature is
+/// enabled, else `SmolSpawner` if the `smol` feature is
+/// enabled, and `AsyncStdSpawner` otherwise.
+#[cfg(all(feature = "smol", not(feature = "tokio")))]
+pub type DefaultSpawner = SmolSpawner;
+
+/// The spawner used by `Scope::create` and the `scope*`
+/// functions: `TokioSpawner` if the `tokio` feature is
+/// enabled, else `SmolSpawner` if the `smol` feature is
+/// enabled, and `AsyncStdSpawner` otherwise.
+#[cfg(all(feature = "async-std", not(any(feature = "tokio", feature = "smol"))))]
+pub type DefaultSpawner = AsyncStdSpawner;
+
+/// The spawner used by `LocalScope::create` and
+/// `local_scope_and_collect`: `TokioSpawner` if the `tokio`
+/// feature is enabled, and `AsyncStdSpawner` otherwise.
+/// `SmolSpawner` does not support local futures, as smol
+/// has no executor local to the current thread.
+#[cfg(feature = "tokio")]
+pub type DefaultLocalSpawner = TokioSpawner;
+
+/// The spawner used by `LocalScope::create` and
+/// `local_scope_and_collect`: `TokioSpawner` if the `tokio`
+/// feature is enabled, and `AsyncStdSpawner` otherwise.
+/// `SmolSpawner` does not support local futures, as smol
+/// has no executor local to the current thread.
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub type DefaultLocalSpawner = AsyncStdSpawner;
+
+#[cfg(feature = "test-util")]
+pub use self::inline_impl::InlineSpawner;
+
+#[cfg(feature = "test-util")]
+mod inline_impl {
+    use std::future::Future;
+    use futures::FutureExt;
+    use futures::future::BoxFuture;
+    use super::Spawner;
+
+    /// Does not spawn the futures onto an executor: the
+    /// futures are driven by the `Scope` itself, on the
+    /// thread polling it. Used by `Scope::create_deterministic`.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct InlineSpawner;
+
+    unsafe impl Spawner for InlineSpawner {
+        type Handle<T> = BoxFuture<'static, T>;
+
+        #[inline]
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            f.boxed()
+        }
+
+        /// The futures are driven by the scope alone, hence
+        /// a detached future is driven on a thread of its
+        /// own.
+        fn spawn_detached<F>(&self, f: F)
+        where F: Future<Output=()> + Send + 'static
+        {
+            std::thread::spawn(move || futures::executor::block_on(f));
+        }
+
+        #[inline]
+        fn block_on<F: Future>(f: F) -> F::Output {
+            futures::executor::block_on(f)
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+pub use self::async_std_impl::AsyncStdSpawner;
+
+#[cfg(feature = "async-std")]
+mod async_std_impl {
+    use std::future::Future;
+    use super::{Spawner, LocalSpawner};
+
+    /// Spawns futures using `async_std::task::spawn`.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct AsyncStdSpawner;
+
+    unsafe impl Spawner for AsyncStdSpawner {
+        type Handle<T> = async_std::task::JoinHandle<T>;
+
+        #[inline]
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            async_std::task::spawn(f)
+        }
+
+        #[inline]
+        fn spawn_blocking<F, T>(&self, f: F) -> Self::Handle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+        {
+            async_std::task::spawn_blocking(f)
+        }
+
+        #[inline]
+        fn block_on<F: Future>(f: F) -> F::Output {
+            async_std::task::block_on(f)
+        }
+    }
+
+    unsafe impl LocalSpawner for AsyncStdSpawner {
+        #[inline]
+        fn spawn_local<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + 'static, F::Output: 'static
+        {
+            async_std::task::spawn_local(f)
+        }
+    }
+}
+
+#[cfg(feature = "smol")]
+pub use self::smol_impl::SmolSpawner;
+
+#[cfg(feature = "smol")]
+mod smol_impl {
+    use std::future::Future;
+    use super::Spawner;
+
+    /// Spawns futures onto the global executor of smol,
+    /// using `smol::spawn`. Dropping a `smol::Task` cancels
+    /// the task, and hence the tasks are never detached:
+    /// the scope retains each task until it completes.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct SmolSpawner;
+
+    unsafe impl Spawner for SmolSpawner {
+        type Handle<T> = smol::Task<T>;
+
+        #[inline]
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            smol::spawn(f)
+        }
+
+        #[inline]
+        fn spawn_blocking<F, T>(&self, f: F) -> Self::Handle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+        {
+            smol::unblock(f)
+        }
+
+        #[inline]
+        fn spawn_detached<F>(&self, f: F)
+        where F: Future<Output=()> + Send + 'static
+        {
+            smol::spawn(f).detach()
+        }
+
+        #[inline]
+        fn block_on<F: Future>(f: F) -> F::Output {
+            smol::block_on(f)
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::tokio_impl::{TokioSpawner, TokioHandle};
+#[cfg(feature = "tokio")]
+pub(crate) use self::tokio_impl::assert_can_block;
+
+#[cfg(feature = "tokio")]
+mod tokio_impl {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Poll, Context};
+    use super::{Spawner, LocalSpawner};
+
+    /// Panics if the current thread runs the current-thread
+    /// runtime, which can not drive the spawned futures
+    /// once the thread blocks on them.
+    pub(crate) fn assert_can_block() {
+        use tokio::runtime::{Handle, RuntimeFlavor};
+        if let Ok(handle) = Handle::try_current() {
+            assert!(handle.runtime_flavor() != RuntimeFlavor::CurrentThread,
+                    "can not block on a scope within the current-thread runtime; \
+                     see `scope_and_block_on`");
+        }
+    }
+
+    /// Spawns futures using `tokio::task::spawn`, or onto a
+    /// specific runtime if created via `with_handle`. The
+    /// futures must be spawned from within a multi-threaded
+    /// runtime: `block_on` uses `tokio::task::block_in_place`,
+    /// which is not supported by the current-thread runtime.
+    #[derive(Debug, Default, Clone)]
+    pub struct TokioSpawner {
+        handle: Option<tokio::runtime::Handle>,
+    }
+
+    impl TokioSpawner {
+        /// Spawn onto the runtime of `handle`, instead of the
+        /// ambient one. The futures may then be spawned (and
+        /// blocked on) from a thread outside any runtime.
+        /// Local futures are still spawned onto the current
+        /// `LocalSet`.
+        pub fn with_handle(handle: tokio::runtime::Handle) -> Self {
+            TokioSpawner { handle: Some(handle) }
+        }
+
+        /// The runtime the futures are spawned onto, if not
+        /// the ambient one.
+        #[inline]
+        pub fn handle(&self) -> Option<&tokio::runtime::Handle> {
+            self.handle.as_ref()
+        }
+    }
+
+    /// A wrapper around `tokio::task::JoinHandle` that
+    /// yields the output of the task directly, resuming the
+    /// panic (if any) on the awaiting task. This keeps the
+    /// output type identical to the async-std backend.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task was cancelled, as it is when the
+    /// runtime shuts down before the task completes: there
+    /// is no output to yield then. The future of the task
+    /// has been dropped by the runtime, so the data it
+    /// borrows is no longer used.
+    #[derive(Debug)]
+    pub struct TokioHandle<T>(tokio::task::JoinHandle<T>);
+
+    impl<T> Future for TokioHandle<T> {
+        type Output = T;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context)
+                -> Poll<Self::Output> {
+            Pin::new(&mut self.0).poll(cx).map(|res| match res {
+                Ok(output) => output,
+                Err(err) => match err.try_into_panic() {
+                    Ok(payload) => std::panic::resume_unwind(payload),
+                    // We never abort the tasks we spawn, but the
+                    // runtime cancels them as it shuts down.
+                    Err(_) => panic!("scoped task was cancelled, as its runtime shut down"),
+                },
+            })
+        }
+    }
+
+    unsafe impl Spawner for TokioSpawner {
+        type Handle<T> = TokioHandle<T>;
+
+        #[inline]
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            match &self.handle {
+                Some(handle) => TokioHandle(handle.spawn(f)),
+                None => TokioHandle(tokio::task::spawn(f)),
+            }
+        }
+
+        /// The name is visible in `tokio-console`; this
+        /// requires building with `--cfg tokio_unstable`,
+        /// and the `tracing` feature.
+        #[cfg(all(tokio_unstable, feature = "tracing"))]
+        fn spawn_named<F>(&self, name: &str, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            let builder = tokio::task::Builder::new().name(name);
+            let join = match &self.handle {
+                Some(handle) => builder.spawn_on(f, handle),
+                None => builder.spawn(f),
+            };
+            TokioHandle(join.expect("failed to spawn a named task"))
+        }
+
+        #[inline]
+        fn spawn_blocking<F, T>(&self, f: F) -> Self::Handle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+        {
+            match &self.handle {
+                Some(handle) => TokioHandle(handle.spawn_blocking(f)),
+                None => TokioHandle(tokio::task::spawn_blocking(f)),
+            }
+        }
+
+        #[inline]
+        fn block_on<F: Future>(f: F) -> F::Output {
+            tokio::task::block_in_place(|| futures::executor::block_on(f))
+        }
+    }
+
+    /// Local futures are spawned on the current
+    /// `tokio::task::LocalSet`, via `tokio::task::spawn_local`.
+    unsafe impl LocalSpawner for TokioSpawner {
+        #[inline]
+        fn spawn_local<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + 'static, F::Output: 'static
+        {
+            TokioHandle(tokio::task::spawn_local(f))
+        }
+    }
+}
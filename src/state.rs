@@ -0,0 +1,417 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Poll, Context};
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use futures::channel::mpsc::{self, UnboundedSender, UnboundedReceiver};
+use futures::future::{AbortHandle, BoxFuture};
+use futures::task::AtomicWaker;
+use slab::Slab;
+
+use crate::{Cancellation, CaughtPanic, Spawner, ScopeHandle, TimingStart};
+use crate::cancellation::AbortOnPanic;
+use crate::context::{ScopeContext, WithContext};
+use crate::handle::{self, Orphans, Completion};
+use crate::limit::Limiter;
+use crate::log::{LogBuffer, LogLine};
+use crate::progress::ProgressEvent;
+
+/// A callback invoked with the index and the duration of
+/// each spawned future that completes.
+pub(crate) type OnComplete = dyn Fn(usize, Duration) + Send + Sync;
+
+/// A callback invoked with the index and the payload of
+/// each spawned future that panics.
+pub(crate) type PanicHook = dyn Fn(usize, &(dyn Any + Send)) + Send + Sync;
+
+/// State shared by a `Scope`, its `SpawnHandle`s, and the
+/// futures spawned in it. `H` is the type of the join
+/// handles of the spawned futures.
+pub(crate) struct State<T, H> {
+    cancellation: OnceLock<Arc<Cancellation>>,
+    pub orphans: Arc<Orphans<T>>,
+    pub limiter: Arc<Limiter>,
+    catch_panics: AtomicBool,
+    abort_on_panic: AtomicBool,
+    next_index: AtomicUsize,
+    completed: Arc<AtomicUsize>,
+    max_depth: AtomicUsize,
+    aborts: Arc<Mutex<Slab<Option<AbortHandle>>>>,
+    on_complete: Arc<Mutex<Option<Arc<OnComplete>>>>,
+    panic_hook: Arc<Mutex<Option<Arc<PanicHook>>>>,
+    timing: Mutex<Option<TimingStart>>,
+    timings: Arc<Mutex<HashMap<usize, Duration>>>,
+    logs: LogBuffer,
+    context: Mutex<Option<ScopeContext>>,
+    progress_tx: UnboundedSender<ProgressEvent>,
+    progress_rx: Mutex<Option<UnboundedReceiver<ProgressEvent>>>,
+    #[cfg(feature = "tracing")]
+    span_name: Mutex<&'static str>,
+    incoming: Mutex<Incoming<H>>,
+    incoming_waker: AtomicWaker,
+}
+
+/// Futures spawned via a `SpawnHandle`, yet to be tracked
+/// by the `Scope`.
+struct Incoming<H> {
+    joins: Vec<H>,
+    closed: bool,
+}
+
+impl<T: Send + 'static, H> State<T, H> {
+    pub fn new() -> Self {
+        let (progress_tx, progress_rx) = mpsc::unbounded();
+        State {
+            cancellation: OnceLock::new(),
+            orphans: Arc::new(Orphans::new()),
+            limiter: Arc::new(Limiter::new()),
+            catch_panics: AtomicBool::new(true),
+            abort_on_panic: AtomicBool::new(false),
+            next_index: AtomicUsize::new(0),
+            completed: Arc::new(AtomicUsize::new(0)),
+            max_depth: AtomicUsize::new(usize::MAX),
+            aborts: Arc::new(Mutex::new(Slab::new())),
+            on_complete: Arc::new(Mutex::new(None)),
+            panic_hook: Arc::new(Mutex::new(None)),
+            timing: Mutex::new(None),
+            timings: Arc::new(Mutex::new(HashMap::new())),
+            logs: Arc::new(Mutex::new(Vec::new())),
+            context: Mutex::new(None),
+            progress_tx,
+            progress_rx: Mutex::new(Some(progress_rx)),
+            #[cfg(feature = "tracing")]
+            span_name: Mutex::new("task"),
+            incoming: Mutex::new(Incoming {
+                joins: Vec::new(),
+                closed: false,
+            }),
+            incoming_waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Wrap a future to be spawned in the scope, assigning
+    /// it the next index.
+    pub fn wrap<'a, F>(&self, f: F, abortable: bool)
+        -> (impl Future<Output=Completion<T>> + Send + 'a, ScopeHandle<'a, T>)
+    where F: Future<Output=T> + Send + 'a
+    {
+        self.wrap_weighted(f, abortable, 1)
+    }
+
+    /// Similar to `wrap`, but the future counts `weight`
+    /// towards the concurrency limit.
+    pub fn wrap_weighted<'a, F>(&self, f: F, abortable: bool, weight: usize)
+        -> (impl Future<Output=Completion<T>> + Send + 'a, ScopeHandle<'a, T>)
+    where F: Future<Output=T> + Send + 'a
+    {
+        self.wrap_at(self.reserve_index(), f, abortable, weight)
+    }
+
+    /// Similar to `wrap_weighted`, but the future is
+    /// assigned `index`, as obtained from `reserve_index`.
+    pub fn wrap_at<'a, F>(&self, index: usize, f: F, abortable: bool, weight: usize)
+        -> (impl Future<Output=Completion<T>> + Send + 'a, ScopeHandle<'a, T>)
+    where F: Future<Output=T> + Send + 'a
+    {
+        // The span is a child of the one active at spawn
+        // time, and is entered only while `f` is polled.
+        #[cfg(feature = "tracing")]
+        let f = tracing::Instrument::instrument(f, tracing::info_span!(
+            "scope.task",
+            scope.name = *self.span_name.lock().unwrap(),
+            scope.task_id = index,
+        ));
+
+        let f = WithContext::new(self.context.lock().unwrap().clone(), f);
+
+        let in_flight = self.limiter.enter(weight);
+        let abort_on_panic = self.abort_on_panic.load(Ordering::SeqCst)
+            .then(|| AbortOnPanic(self.cancellation().clone()));
+        // The abort handle is registered once created below,
+        // until `f` completes or is dropped.
+        let abort_key = abortable.then(|| {
+            let key = self.aborts.lock().unwrap().insert(None);
+            Deregister(self.aborts.clone(), key)
+        });
+        let registered = abort_key.as_ref().map(|guard| guard.1);
+        let start = Instant::now();
+        let timing = *self.timing.lock().unwrap();
+        let timings = self.timings.clone();
+        let f = async move {
+            let _abort_key = abort_key;
+            let _abort_on_panic = abort_on_panic;
+            let polled = Instant::now();
+            let output = f.await;
+            std::mem::drop(in_flight);
+            let elapsed = match timing {
+                Some(TimingStart::Spawn) => Some(start.elapsed()),
+                Some(TimingStart::FirstPoll) => Some(polled.elapsed()),
+                None => None,
+            };
+            if let Some(elapsed) = elapsed {
+                timings.lock().unwrap().insert(index, elapsed);
+            }
+            output
+        };
+
+        let on_complete = self.on_complete.clone();
+        let panic_hook = self.panic_hook.clone();
+        let completed = self.completed.clone();
+        let finish = move |panic: Option<&CaughtPanic>| {
+            completed.fetch_add(1, Ordering::SeqCst);
+            if let Some(panic) = panic {
+                let hook = panic_hook.lock().unwrap().clone();
+                if let Some(hook) = hook {
+                    hook(index, panic.payload());
+                }
+            }
+            let callback = on_complete.lock().unwrap().clone();
+            if let Some(callback) = callback {
+                callback(index, start.elapsed());
+            }
+        };
+        let (task, handle) = handle::wrap(f, self.orphans.clone(), index,
+                                          self.catch_panics(), abortable, finish);
+        if let Some(key) = registered {
+            if let Some(slot) = self.aborts.lock().unwrap().get_mut(key) {
+                *slot = Some(handle.abort_handle());
+            }
+        }
+        (task, handle)
+    }
+}
+
+impl<T, H> State<T, H> {
+    /// Number of futures spawned so far, including those
+    /// spawned via `SpawnHandle`s.
+    pub fn total_spawned(&self) -> usize {
+        self.next_index.load(Ordering::SeqCst)
+    }
+
+    /// Number of futures that completed (including those
+    /// that panicked, if panics are caught), whether or not
+    /// their output has been yielded.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::SeqCst)
+    }
+
+    /// The cancellation of the scope, created on first use:
+    /// scopes that spawn no cancellable futures do not
+    /// allocate it.
+    pub fn cancellation(&self) -> &Arc<Cancellation> {
+        self.cancellation.get_or_init(|| Arc::new(Cancellation::new()))
+    }
+
+    /// Number of futures that were cancelled.
+    pub fn cancelled(&self) -> usize {
+        self.cancellation.get().map_or(0, |cancellation| cancellation.cancelled())
+    }
+
+    /// Number of cancellable futures alive.
+    pub fn live_cancellable(&self) -> usize {
+        self.cancellation.get().map_or(0, |cancellation| cancellation.live())
+    }
+
+    /// Ready once no cancellable futures are alive.
+    pub fn poll_idle(&self, cx: &mut Context) -> Poll<()> {
+        match self.cancellation.get() {
+            Some(cancellation) => cancellation.poll_idle(cx),
+            None => Poll::Ready(()),
+        }
+    }
+
+    /// Abort the pending abortable futures, as in
+    /// `ScopeHandle::abort`.
+    pub fn abort_pending(&self) {
+        for (_, abort) in self.aborts.lock().unwrap().iter() {
+            if let Some(abort) = abort {
+                abort.abort();
+            }
+        }
+    }
+
+    /// Assign the next index, for a future to be wrapped
+    /// via `wrap_at`.
+    pub fn reserve_index(&self) -> usize {
+        self.next_index.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub fn logs(&self) -> LogBuffer {
+        self.logs.clone()
+    }
+
+    /// The lines logged so far, in order; see
+    /// `Scope::take_logs`.
+    pub fn take_logs(&self) -> Vec<LogLine> {
+        std::mem::take(&mut *self.logs.lock().unwrap())
+    }
+
+    pub fn progress_tx(&self) -> UnboundedSender<ProgressEvent> {
+        self.progress_tx.clone()
+    }
+
+    /// The receiver of the progress events, unless already
+    /// taken.
+    pub fn take_progress_rx(&self) -> Option<UnboundedReceiver<ProgressEvent>> {
+        self.progress_rx.lock().unwrap().take()
+    }
+
+    pub fn catch_panics(&self) -> bool {
+        self.catch_panics.load(Ordering::SeqCst)
+    }
+
+    pub fn set_catch_panics(&self, catch: bool) {
+        self.catch_panics.store(catch, Ordering::SeqCst);
+    }
+
+    pub fn set_abort_on_panic(&self, abort: bool) {
+        self.abort_on_panic.store(abort, Ordering::SeqCst);
+    }
+
+    /// The maximum depth of `SpawnHandle`s, if bounded.
+    pub fn max_depth(&self) -> Option<usize> {
+        match self.max_depth.load(Ordering::SeqCst) {
+            usize::MAX => None,
+            depth => Some(depth),
+        }
+    }
+
+    pub fn set_max_depth(&self, depth: usize) {
+        self.max_depth.store(depth, Ordering::SeqCst);
+    }
+
+    pub fn set_on_complete(&self, callback: Option<Arc<OnComplete>>) {
+        *self.on_complete.lock().unwrap() = callback;
+    }
+
+    pub fn set_panic_hook(&self, hook: Option<Arc<PanicHook>>) {
+        *self.panic_hook.lock().unwrap() = hook;
+    }
+
+    /// Time the futures spawned hereafter; see
+    /// `take_timing`.
+    pub fn set_timing(&self, start: TimingStart) {
+        *self.timing.lock().unwrap() = Some(start);
+    }
+
+    /// The duration of the future of index `index`, if it
+    /// was timed and completed without panicking.
+    pub fn take_timing(&self, index: usize) -> Option<Duration> {
+        self.timings.lock().unwrap().remove(&index)
+    }
+
+    pub fn set_context(&self, context: Option<ScopeContext>) {
+        *self.context.lock().unwrap() = context;
+    }
+
+    #[cfg(feature = "tracing")]
+    pub fn set_span_name(&self, name: &'static str) {
+        *self.span_name.lock().unwrap() = name;
+    }
+
+    /// Track a future spawned via `spawn`, which is only
+    /// called if the scope has not been closed. Returns
+    /// whether the future was spawned.
+    pub fn push_incoming(&self, spawn: impl FnOnce() -> H) -> bool {
+        let mut incoming = self.incoming.lock().unwrap();
+        if incoming.closed {
+            return false;
+        }
+        incoming.joins.push(spawn());
+        std::mem::drop(incoming);
+        self.incoming_waker.wake();
+        true
+    }
+
+    /// Take the futures spawned via `SpawnHandle`s,
+    /// registering the waker from `cx` to be woken when
+    /// more are spawned.
+    pub fn poll_incoming(&self, cx: &mut Context) -> Vec<H> {
+        self.incoming_waker.register(cx.waker());
+        std::mem::take(&mut self.incoming.lock().unwrap().joins)
+    }
+
+    /// Number of futures spawned via `SpawnHandle`s, yet to
+    /// be tracked by the scope.
+    pub fn incoming_len(&self) -> usize {
+        self.incoming.lock().unwrap().joins.len()
+    }
+
+    /// Disallow further spawns via `SpawnHandle`s, unless
+    /// there are spawned futures yet to be tracked. Returns
+    /// whether the scope was closed.
+    pub fn try_close(&self) -> bool {
+        let mut incoming = self.incoming.lock().unwrap();
+        if incoming.joins.is_empty() {
+            incoming.closed = true;
+        }
+        incoming.closed
+    }
+}
+
+/// Guard removing the abort handle of a spawned future from
+/// the scope's registry once the future is done.
+struct Deregister(Arc<Mutex<Slab<Option<AbortHandle>>>>, usize);
+
+impl Drop for Deregister {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().remove(self.1);
+    }
+}
+
+/// Spawn a wrapped future via `spawner`.
+///
+/// # Safety
+///
+/// The returned join handle must be driven to completion
+/// before 'a expires.
+pub(crate) unsafe fn spawn_task<'a, T, Sp, F>(spawner: &Sp, task: F)
+                                              -> Sp::Handle<Completion<T>>
+where T: Send + 'static, Sp: Spawner,
+      F: Future<Output=Completion<T>> + Send + 'a
+{
+    spawner.spawn(
+        std::mem::transmute::<BoxFuture<'a, Completion<T>>,
+                              BoxFuture<'static, Completion<T>>>(task.boxed())
+    )
+}
+
+/// Similar to `spawn_task`, but the future is named `name`;
+/// see `Spawner::spawn_named`.
+///
+/// # Safety
+///
+/// The same considerations as `spawn_task` apply.
+pub(crate) unsafe fn spawn_task_named<'a, T, Sp, F>(spawner: &Sp, name: &str, task: F)
+                                                    -> Sp::Handle<Completion<T>>
+where T: Send + 'static, Sp: Spawner,
+      F: Future<Output=Completion<T>> + Send + 'a
+{
+    spawner.spawn_named(
+        name,
+        std::mem::transmute::<BoxFuture<'a, Completion<T>>,
+                              BoxFuture<'static, Completion<T>>>(task.boxed())
+    )
+}
+
+/// Similar to `spawn_task`, but the future is spawned onto
+/// one of `workers`; see `Spawner::spawn_on_workers`.
+///
+/// # Safety
+///
+/// The same considerations as `spawn_task` apply.
+pub(crate) unsafe fn spawn_task_on<'a, T, Sp, F>(spawner: &Sp, workers: &[usize], task: F)
+                                                 -> Sp::Handle<Completion<T>>
+where T: Send + 'static, Sp: Spawner,
+      F: Future<Output=Completion<T>> + Send + 'a
+{
+    spawner.spawn_on_workers(
+        workers,
+        std::mem::transmute::<BoxFuture<'a, Completion<T>>,
+                              BoxFuture<'static, Completion<T>>>(task.boxed())
+    )
+}
@@ -0,0 +1,45 @@
+use std::any::{Any, TypeId};
+use std::hash::{Hash, Hasher};
+
+/// A tag of `Scope::spawn_tagged`, of any type: tags of
+/// different types are never equal.
+pub(crate) struct Tag(Box<dyn TagKey>);
+
+impl Tag {
+    pub fn new<K: Hash + Eq + Send + Sync + 'static>(key: K) -> Self {
+        Tag(Box::new(key))
+    }
+}
+
+trait TagKey: Any + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_key(&self, other: &dyn TagKey) -> bool;
+    fn hash_key(&self, state: &mut dyn Hasher);
+}
+
+impl<K: Hash + Eq + Send + Sync + 'static> TagKey for K {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn eq_key(&self, other: &dyn TagKey) -> bool {
+        other.as_any().downcast_ref::<K>() == Some(self)
+    }
+
+    fn hash_key(&self, mut state: &mut dyn Hasher) {
+        TypeId::of::<K>().hash(&mut state);
+        self.hash(&mut state);
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Tag) -> bool {
+        self.0.eq_key(&*other.0)
+    }
+}
+
+impl Eq for Tag {}
+
+impl Hash for Tag {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_key(state);
+    }
+}
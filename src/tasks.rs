@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::task::{Poll, Context};
+use std::pin::Pin;
+
+use futures::{Stream, Future, StreamExt};
+use futures::stream::{FuturesUnordered, FuturesOrdered};
+
+/// The collection of spawned futures driven by a `Scope`.
+/// The outputs are yielded either in the order of
+/// completion, or in the order of spawning.
+pub(crate) enum Tasks<F: Future> {
+    Unordered(FuturesUnordered<F>),
+    Ordered(FuturesOrdered<F>),
+    Windowed(Windowed<F>),
+    #[cfg(feature = "test-util")]
+    Seeded(Seeded<F>),
+}
+
+impl<F: Future> Tasks<F> {
+    pub fn unordered() -> Self {
+        Tasks::Unordered(FuturesUnordered::new())
+    }
+
+    pub fn ordered() -> Self {
+        Tasks::Ordered(FuturesOrdered::new())
+    }
+
+    pub fn windowed(window: usize) -> Self {
+        Tasks::Windowed(Windowed { futs: VecDeque::new(), window })
+    }
+
+    #[cfg(feature = "test-util")]
+    pub fn seeded(seed: u64) -> Self {
+        Tasks::Seeded(Seeded { futs: Vec::new(), state: seed })
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        matches!(self, Tasks::Ordered(_) | Tasks::Windowed(_))
+    }
+
+    /// The window of a windowed collection; see
+    /// `Scope::with_ordered_window`.
+    pub fn window(&self) -> Option<usize> {
+        match self {
+            Tasks::Windowed(futs) => Some(futs.window),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Tasks::Unordered(futs) => futs.len(),
+            Tasks::Ordered(futs) => futs.len(),
+            Tasks::Windowed(futs) => futs.futs.len(),
+            #[cfg(feature = "test-util")]
+            Tasks::Seeded(futs) => futs.futs.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Tasks::Unordered(futs) => futs.is_empty(),
+            Tasks::Ordered(futs) => futs.is_empty(),
+            Tasks::Windowed(futs) => futs.futs.is_empty(),
+            #[cfg(feature = "test-util")]
+            Tasks::Seeded(futs) => futs.futs.is_empty(),
+        }
+    }
+
+    /// Reserve room for `additional` more futures, where
+    /// the collection supports it: `FuturesUnordered` and
+    /// `FuturesOrdered` allocate each future separately,
+    /// and can not be presized.
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            Tasks::Unordered(_) | Tasks::Ordered(_) => {},
+            Tasks::Windowed(futs) => futs.futs.reserve(additional),
+            #[cfg(feature = "test-util")]
+            Tasks::Seeded(futs) => futs.futs.reserve(additional),
+        }
+    }
+
+    pub fn push(&mut self, fut: F) {
+        match self {
+            Tasks::Unordered(futs) => futs.push(fut),
+            Tasks::Ordered(futs) => futs.push_back(fut),
+            Tasks::Windowed(futs) => futs.futs.push_back(Slot::Pending(Box::pin(fut))),
+            #[cfg(feature = "test-util")]
+            Tasks::Seeded(futs) => futs.futs.push(Box::pin(fut)),
+        }
+    }
+}
+
+impl<F: Future> Stream for Tasks<F> {
+    type Item = F::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Tasks::Unordered(futs) => futs.poll_next_unpin(cx),
+            Tasks::Ordered(futs) => futs.poll_next_unpin(cx),
+            Tasks::Windowed(futs) => futs.poll_next(cx),
+            #[cfg(feature = "test-util")]
+            Tasks::Seeded(futs) => futs.poll_next(cx),
+        }
+    }
+}
+
+/// Futures whose outputs are yielded in the order they
+/// were pushed, but of which only the first `window` are
+/// polled. The scope pushes at most `window` of them at a
+/// time, holding back the later ones until the first are
+/// yielded.
+pub(crate) struct Windowed<F: Future> {
+    futs: VecDeque<Slot<F>>,
+    window: usize,
+}
+
+enum Slot<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(F::Output),
+}
+
+// The futures are boxed, and the outputs are never pinned.
+impl<F: Future> Unpin for Windowed<F> {}
+
+impl<F: Future> Windowed<F> {
+    fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<F::Output>> {
+        if self.futs.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        for slot in self.futs.iter_mut().take(self.window) {
+            if let Slot::Pending(fut) = slot {
+                if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+                    *slot = Slot::Done(output);
+                }
+            }
+        }
+
+        if let Some(Slot::Done(_)) = self.futs.front() {
+            match self.futs.pop_front() {
+                Some(Slot::Done(output)) => Poll::Ready(Some(output)),
+                _ => unreachable!(),
+            }
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Futures that are polled in a pseudo-random order derived
+/// from a seed: each poll visits the pending futures in a
+/// fresh permutation, and yields the first ready output.
+/// Given futures that are themselves deterministic, the
+/// order of the outputs is reproducible.
+#[cfg(feature = "test-util")]
+pub(crate) struct Seeded<F> {
+    futs: Vec<Pin<Box<F>>>,
+    state: u64,
+}
+
+#[cfg(feature = "test-util")]
+impl<F: Future> Seeded<F> {
+    /// The next pseudo-random number (splitmix64).
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<F::Output>> {
+        if self.futs.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        // Fisher-Yates shuffle of the polling order
+        let mut order: Vec<usize> = (0..self.futs.len()).collect();
+        for i in (1..order.len()).rev() {
+            let j = (self.next_random() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+
+        for i in order {
+            if let Poll::Ready(output) = self.futs[i].as_mut().poll(cx) {
+                std::mem::drop(self.futs.remove(i));
+                return Poll::Ready(Some(output));
+            }
+        }
+        Poll::Pending
+    }
+}
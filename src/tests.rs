@@ -1,4 +1,33 @@
-#[async_std::test]
+use std::time::Duration;
+
+// The tests run under async-std, or else tokio: without
+// either (say, with `smol` alone), tokio (a dev-dependency)
+// merely drives the tests, and the futures are spawned via
+// the `DefaultSpawner`.
+
+/// Executor-agnostic sleep used by the tests.
+async fn sleep(dur: Duration) {
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    async_std::task::sleep(dur).await;
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(dur).await;
+    #[cfg(all(feature = "smol", not(any(feature = "async-std", feature = "tokio"))))]
+    smol::Timer::after(dur).await;
+}
+
+/// Executor-agnostic timeout used by the tests: `None`
+/// denotes that `dur` elapsed before `fut` resolved.
+async fn timeout<F: std::future::Future + Unpin>(dur: Duration, fut: F)
+                                                 -> Option<F::Output> {
+    use futures::future::{select, Either};
+    match select(fut, Box::pin(sleep(dur))).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
 async fn scope() {
     let not_copy = String::from("hello world!");
     let not_copy_ref = &not_copy;
@@ -25,7 +54,8 @@ async fn scope() {
 
 /// Test scope bounds: should allow any future with lifetime
 /// larger than the scope's lifetime
-#[async_std::test]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
 async fn scope_lifetime() {
     use std::future::Future;
     let static_fut = futures::future::ready(());
@@ -47,24 +77,20 @@ async fn scope_lifetime() {
 
 }
 
-#[async_std::test]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
 async fn scope_async() {
     let not_copy = String::from("hello world!");
     let not_copy_ref = &not_copy;
 
     let stream = unsafe {
-        use async_std::future::{timeout, pending};
-        use std::time::Duration;
         let mut s = crate::Scope::create();
         for _ in 0..10 {
             let proc = || async move {
                 assert_eq!(not_copy_ref, "hello world!");
             };
             s.spawn(proc());
-            let _ = timeout(
-                Duration::from_millis(10),
-                pending::<()>(),
-            ).await;
+            sleep(Duration::from_millis(10)).await;
         }
         s
     };
@@ -81,7 +107,8 @@ async fn scope_async() {
 }
 
 
-#[async_std::test]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
 async fn scope_and_collect() {
     let not_copy = String::from("hello world!");
     let not_copy_ref = &not_copy;
@@ -98,7 +125,8 @@ async fn scope_and_collect() {
     assert_eq!(vals.len(), 10);
 }
 
-#[async_std::test]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
 async fn scope_and_block() {
     let not_copy = String::from("hello world!");
     let not_copy_ref = &not_copy;
@@ -115,89 +143,3113 @@ async fn scope_and_block() {
     assert_eq!(vals.len(), 10);
 }
 
-/// This is a simplified version of the soundness bug
-/// pointed out on [reddit][reddit-ref]. Here, we test that
-/// it does not happen when using the `scope_and_collect`,
-/// but the returned future is not forgotten. Forgetting the
-/// future should lead to an invalid memory access.
-///
-/// [reddit-ref]: https://www.reddit.com/r/rust/comments/ee3vsu/asyncscoped_spawn_non_static_futures_with_asyncstd/fbpis3c?utm_source=share&utm_medium=web2x
-#[async_std::test]
-async fn cancellation_soundness() {
-    use async_std::future;
-    use std::time::*;
+#[cfg(feature = "async-std")]
+#[test]
+fn scope_and_block_on() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
 
-    async fn inner() {
-        let mut shared = true;
-        let shared_ref = &mut shared;
+    // The single-threaded executor is blocked, while the
+    // futures are driven by async-std.
+    let ((), vals) = futures::executor::block_on(async {
+        crate::scope_and_block_on(crate::AsyncStdSpawner, |s| {
+            for _ in 0..10 {
+                s.spawn(async move { not_copy_ref.len() });
+            }
+        })
+    });
 
-        let start = Instant::now();
+    assert_eq!(vals, vec![12; 10]);
+}
 
-        let mut fut = Box::pin(
-            unsafe { crate::scope_and_collect(|scope| {
-                scope.spawn_cancellable(async {
-                    assert!(future::timeout(
-                        Duration::from_millis(500),
-                        future::pending::<()>(),
-                    ).await.is_err());
+#[cfg(feature = "threads")]
+#[test]
+fn thread_scope() {
+    let data: Vec<usize> = (0..100).collect();
+    let (len, sums) = crate::thread_scope(|s| {
+        for chunk in data.chunks(10) {
+            s.spawn_thread(move || chunk.iter().sum::<usize>());
+        }
+        s.len()
+    });
+    assert_eq!(len, 10);
+    assert_eq!(sums.len(), 10);
+    assert_eq!(sums[0], 45);
+    assert_eq!(sums.iter().sum::<usize>(), data.iter().sum());
 
-                    eprintln!("Trying to write to shared_ref");
-                    *shared_ref = false;
-                    assert!(*shared_ref);
-                }, || ());
-            })}
-        );
-        let _ = future::timeout(Duration::from_millis(10), &mut fut).await;
+    let result = std::panic::catch_unwind(|| {
+        crate::thread_scope(|s| {
+            s.spawn_thread(|| 1);
+            s.spawn_thread(|| panic!("thread panicked"));
+        })
+    });
+    assert!(result.is_err());
+}
 
-        // Dropping explicitly to measure time taken to complete drop.
-        // Change the drop to forget for panic due to invalid mem. access.
-        std::mem::drop(fut);
-        let elapsed = start.elapsed().as_millis();
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_block_with() {
+    let limit = 4;
+    let words: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+    let words_ref = &words;
+    let (drained, mut lens) = crate::scope_and_block_with(|s| Box::pin(async move {
+        let mut drained = Vec::new();
+        for word in words_ref {
+            while s.remaining() >= limit {
+                drained.extend(s.join_next().await);
+            }
+            s.spawn(async move {
+                sleep(Duration::from_millis(5)).await;
+                word.len()
+            });
+            assert!(s.remaining() <= limit);
+        }
+        drained
+    }));
+    lens.extend(drained);
+    lens.sort();
+    assert_eq!(lens, words.iter().map(|word| word.len()).collect::<Vec<_>>());
+}
 
+#[cfg(feature = "tokio")]
+#[tokio::test]
+#[should_panic(expected = "current-thread runtime")]
+async fn scope_and_block_current_thread() {
+    crate::scope_and_block(|s| {
+        s.spawn(async {});
+    });
+}
 
-        // The cancelled future should have been polled
-        // before the inner large timeout.
-        assert!(elapsed < 100);
-        eprintln!("Elapsed: {}ms", start.elapsed().as_millis());
+/// A task cancelled as its runtime shuts down has no output
+/// to yield, and its handle panics instead.
+#[cfg(feature = "tokio")]
+#[test]
+#[should_panic(expected = "runtime shut down")]
+fn tokio_handle_after_shutdown() {
+    use crate::Spawner;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let spawner = crate::TokioSpawner::with_handle(runtime.handle().clone());
+    let handle = spawner.spawn(futures::future::pending::<()>());
+    std::mem::drop(runtime);
+    futures::executor::block_on(handle);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_block_panic() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn spawn_all<'a>(s: &mut crate::Scope<'a, u64>, done: &'a AtomicBool) {
+        s.spawn(async { panic!("boom") });
+        s.spawn_cancellable(async move {
+            sleep(Duration::from_millis(50)).await;
+            done.store(true, Ordering::SeqCst);
+            1
+        }, || 0);
     }
+    let done = AtomicBool::new(false);
+    let done = &done;
 
-    inner().await;
+    // The panic is resumed once the other future completes.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        crate::scope_and_block(|s| spawn_all(s, done))
+    }));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+    assert!(done.swap(false, Ordering::SeqCst));
 
-    // This timeout allows any (possible) invalid memory
-    // access to actually take place.
-    assert!(future::timeout(Duration::from_millis(600),
-                            future::pending::<()>()).await.is_err());
+    let (_, outputs) = crate::scope_and_block_caught(|s| spawn_all(s, done));
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs.iter().filter(|output| output.is_err()).count(), 1);
+    assert!(outputs.iter().any(|output| matches!(output, Ok(1))));
+    assert!(done.load(Ordering::SeqCst));
+}
 
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn executor() {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    let ((), vals) = unsafe {
+        crate::scope_and_collect(|s| {
+            s.spawn(async { 1 });
+            let executor = s.executor();
+            assert!(executor.name().ends_with("Spawner"));
+            executor.spawn_detached(async move {
+                sleep(Duration::from_millis(10)).await;
+                let _ = tx.send(2);
+            });
+        })
+    }.await;
+    assert_eq!(vals, [1]);
+    assert_eq!(rx.await, Ok(2));
 }
 
-/// This test is resource consuming and ignored by default
-#[async_std::test]
-#[ignore]
-async fn backpressure() {
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn custom_spawner() {
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::{Spawner, DefaultSpawner};
+
+    /// Counts the futures spawned via the default spawner.
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    unsafe impl Spawner for Counting {
+        type Handle<T> = <DefaultSpawner as Spawner>::Handle<T>;
+
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: Future + Send + 'static, F::Output: Send + 'static
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            DefaultSpawner::default().spawn(f)
+        }
+
+        fn block_on<F: Future>(f: F) -> F::Output {
+            DefaultSpawner::block_on(f)
+        }
+    }
+
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let mut s = unsafe { crate::Scope::create_with(Counting::default()) };
+    for _ in 0..10 {
+        s.spawn(async move {
+            assert_eq!(not_copy_ref, "hello world!");
+        });
+    }
+    assert_eq!(s.collect().await.len(), 10);
+    assert_eq!(s.spawner().0.load(Ordering::SeqCst), 10);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_handle() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
     let mut s = unsafe { crate::Scope::create() };
-    let limit = 0x10;
-    for i in 0..0x100 {
+    let handle = s.spawn(async move { not_copy_ref.len() });
+    for _ in 0..10 {
+        s.spawn(async move { not_copy_ref.len() + 1 });
+    }
+    assert_eq!(handle.await, 12);
+
+    // Output of awaited handle is not yielded by the stream
+    let vals = s.collect().await;
+    assert_eq!(vals, vec![13; 10]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_handle_abort() {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let mut s = unsafe { crate::Scope::create() };
+    let slow = s.spawn(async {
+        sleep(Duration::from_millis(5000)).await;
+        1
+    });
+    let fast = s.spawn(async { 2 });
+
+    // Cancel the slow task once the fast one is done
+    assert_eq!(fast.await, 2);
+    assert!(!slow.is_finished());
+    slow.abort();
+
+    assert!(s.collect().await.is_empty());
+    assert!(slow.is_finished());
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_ordered() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let (_, vals) = unsafe { crate::scope_and_collect_ordered(|s| {
+        for i in 0..10u64 {
+            // Later futures complete earlier
+            s.spawn(async move {
+                sleep(Duration::from_millis(10 * (10 - i))).await;
+                assert_eq!(not_copy_ref, "hello world!");
+                i
+            });
+        }
+    }) }.await;
+
+    assert_eq!(vals, (0..10).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_join_all() {
+    let delays = [30, 0, 50, 10, 40, 0, 20];
+    let (block_output, vals) = unsafe { crate::scope_join_all(|s| {
+        for (i, delay) in delays.iter().enumerate() {
+            s.spawn(async move {
+                sleep(Duration::from_millis(*delay)).await;
+                i
+            });
+        }
+        delays.len()
+    }) }.await;
+
+    assert_eq!(block_output, 7);
+    assert_eq!(vals, (0..7).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn try_scope_and_collect() {
+    use std::time::Instant;
+
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let (_, vals) = unsafe { crate::try_scope_and_collect(|s| {
+        for i in 0..10 {
+            s.spawn(async move {
+                assert_eq!(not_copy_ref, "hello world!");
+                Ok::<_, ()>(i)
+            });
+        }
+    }) }.await;
+    assert_eq!(vals.map(|v| v.len()), Ok(10));
+
+    let start = Instant::now();
+    let (_, vals) = unsafe { crate::try_scope_and_collect(|s| {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_millis(5000)).await;
+            Ok(not_copy_ref.len())
+        }, || Ok(0));
         s.spawn(async {
-            // Allocate a large array (256 MB)
-            let blob = vec![42u8; 0x10000000];
+            sleep(Duration::from_millis(10)).await;
+            Err("failed")
+        });
+    }) }.await;
+    assert_eq!(vals, Err("failed"));
+    assert!(start.elapsed() < Duration::from_millis(1000));
 
-            // Spend a lot of time on it asynchronously
-            use async_std::future;
-            use std::time::Duration;
-            let _ = future::timeout(
-                Duration::from_millis(100),
-                future::pending::<()>()
-            ).await;
+    // The first error is reported, and the later ones are
+    // discarded.
+    let (_, vals) = unsafe { crate::try_scope_and_collect(|s| {
+        for (delay, err) in [(10, "first"), (30, "second")] {
+            s.spawn(async move {
+                sleep(Duration::from_millis(delay)).await;
+                Err::<usize, _>(err)
+            });
+        }
+    }) }.await;
+    assert_eq!(vals, Err("first"));
+}
 
-            std::mem::drop(blob);
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn panic_propagation() {
+    use futures::FutureExt;
+    use std::panic::AssertUnwindSafe;
+
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let (_, vals) = unsafe { crate::scope_and_collect_caught(|s| {
+        for i in 0..5 {
+            s.spawn(async move {
+                if i == 3 { panic!("boom"); }
+                not_copy_ref.len()
+            });
+        }
+    }) }.await;
+    assert_eq!(vals.len(), 5);
+    let panics = vals.iter().filter_map(|v| v.as_ref().err()).collect::<Vec<_>>();
+    assert_eq!(panics.len(), 1);
+    assert_eq!(panics[0].index(), Some(3));
+    assert_eq!(panics[0].message(), Some("boom"));
+
+    // Plain collection resumes the panic
+    let result = AssertUnwindSafe(unsafe { crate::scope_and_collect(|s| {
+        s.spawn(async { panic!("boom") });
+    }) }).catch_unwind().await;
+    assert!(result.is_err());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn abort_all() {
+    use std::time::Instant;
+
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let start = Instant::now();
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_cancellable(async { not_copy_ref.len() }, || 0);
+    for _ in 0..5 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_millis(5000)).await;
+            not_copy_ref.len()
+        }, || 0);
+    }
+    s.spawn(async {
+        sleep(Duration::from_millis(100)).await;
+        not_copy_ref.len() + 1
+    });
+
+    // Cancellable futures are driven until aborted
+    assert_eq!(futures::StreamExt::next(&mut s).await, Some(12));
+
+    s.abort_all();
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, vec![0, 0, 0, 0, 0, 13]);
+    assert_eq!(s.remaining(), 0);
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn concurrency_limit() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    let (running, max_running) = (&running, &max_running);
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(4);
+    for i in 0..20 {
+        if i == 10 {
+            // Ramp up after a warmup
+            s.set_concurrency_limit(6);
+        }
+        s.spawn_limited(async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        }).await;
+        assert!(s.in_flight() <= s.concurrency_limit().unwrap());
+    }
+
+    assert_eq!(s.collect().await.len(), 20);
+    assert!(max_running.load(Ordering::SeqCst) <= 6);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_blocking() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let (_, mut vals) = unsafe { crate::scope_and_collect(|s| {
+        for _ in 0..5 {
+            s.spawn_blocking(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                not_copy_ref.len()
+            });
+            s.spawn(async move { not_copy_ref.len() + 1 });
+        }
+    }) }.await;
+
+    vals.sort();
+    assert_eq!(vals, vec![12, 12, 12, 12, 12, 13, 13, 13, 13, 13]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_handle() {
+    fn visit<'a>(h: crate::SpawnHandle<'a, usize>, depth: usize, base: &'a usize) {
+        let child = h.clone();
+        h.spawn(async move {
+            if depth > 0 {
+                visit(child.clone(), depth - 1, base);
+                visit(child, depth - 1, base);
+            }
+            *base + depth
         });
+    }
 
-        while s.remaining() > limit {
-            use futures::StreamExt;
-            s.next().await;
+    let base = 10;
+    let base_ref = &base;
+    let (_, mut vals) = unsafe { crate::scope_and_collect(|s| {
+        visit(s.handle(), 3, base_ref);
+    }) }.await;
+
+    vals.sort();
+    let mut expected = vec![];
+    for depth in 0..4 {
+        for _ in 0..(1 << (3 - depth)) {
+            expected.push(base + depth);
         }
-        eprintln!("Spawned {} futures", i);
     }
+    assert_eq!(vals, expected);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn graceful_shutdown() {
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..3 {
+        s.spawn(async move { i });
+    }
+    for _ in 0..2 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            10
+        }, || 20);
+    }
+    s.spawn(async {
+        sleep(Duration::from_millis(300)).await;
+        30
+    });
+
+    let summary = s.graceful_shutdown(Duration::from_millis(100)).await;
+    assert_eq!(summary.completed, 3);
+    assert_eq!(summary.cancelled, 2);
+    assert_eq!(summary.overdue, 1);
+    assert!(!summary.is_clean());
+
+    let mut vals: Vec<_> = summary.outputs.into_iter().map(Result::unwrap).collect();
+    vals.sort();
+    assert_eq!(vals, vec![0, 1, 2, 20, 20, 30]);
+
+    let summary = s.graceful_shutdown(Duration::from_millis(100)).await;
+    assert!(summary.is_clean());
+    assert!(summary.outputs.is_empty());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn size_hint() {
+    use futures::{Stream, StreamExt};
+
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..5 {
+        s.spawn(async move { i });
+    }
+    assert_eq!(s.size_hint(), (s.remaining(), Some(s.remaining())));
+
+    // Open for spawning via the handle
+    let handle = s.handle();
+    assert_eq!(s.size_hint(), (5, None));
+    handle.spawn(async { 5 });
+    std::mem::drop(handle);
+    assert_eq!(s.size_hint(), (6, Some(6)));
+
+    assert!(s.next().await.is_some());
+    assert_eq!(s.size_hint(), (s.remaining(), Some(s.remaining())));
+    assert_eq!(s.remaining(), 5);
+    assert_eq!(crate::Scope::collect(&mut s).await.len(), 5);
+    assert_eq!(s.size_hint(), (0, Some(0)));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn fused_stream() {
+    use futures::stream::{FusedStream, StreamExt};
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { 1 });
+    s.spawn(async { 2 });
+    assert!(!s.is_terminated());
+
+    let mut sum = 0;
+    loop {
+        futures::select! {
+            val = s.next() => match val {
+                Some(val) => sum += val,
+                None => break,
+            },
+            complete => break,
+        }
+    }
+    assert_eq!(sum, 3);
+    assert!(s.is_terminated());
+
+    s.spawn(async { 3 });
+    assert!(!s.is_terminated());
+    assert_eq!(crate::Scope::collect(&mut s).await, vec![3]);
+    assert!(s.is_terminated());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_weighted() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let weight = AtomicUsize::new(0);
+    let max_weight = AtomicUsize::new(0);
+    let (weight, max_weight) = (&weight, &max_weight);
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(10);
+    for i in 0..20 {
+        let w = 1 + i % 6;
+        s.spawn_weighted(w, async move {
+            let now = weight.fetch_add(w, Ordering::SeqCst) + w;
+            max_weight.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(10)).await;
+            weight.fetch_sub(w, Ordering::SeqCst);
+        }).await;
+        assert!(s.remaining_weight() <= 10);
+    }
+
+    assert_eq!(s.collect().await.len(), 20);
+    assert_eq!(s.remaining_weight(), 0);
+    assert!(max_weight.load(Ordering::SeqCst) <= 10);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_into() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let mut vals = Vec::with_capacity(10);
+    for n in (1..=10).rev() {
+        let ptr = vals.as_ptr();
+        let count = unsafe { crate::scope_and_collect_into(|s| {
+            for _ in 0..n {
+                s.spawn(async move { not_copy_ref.len() });
+            }
+            n
+        }, &mut vals) }.await;
+
+        assert_eq!(vals, vec![12; count]);
+        // The buffer is reused
+        assert_eq!(vals.as_ptr(), ptr);
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn join_next() {
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..5 {
+        s.spawn(async move { i });
+    }
+
+    let mut sum = 0;
+    while let Some(val) = s.join_next().await {
+        sum += val;
+    }
+    assert_eq!(sum, 10);
+    assert_eq!(s.join_next().await, None);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_timing() {
+    use futures::StreamExt;
+
+    for start in [crate::TimingStart::Spawn, crate::TimingStart::FirstPoll] {
+        let mut s = unsafe { crate::Scope::create() }.with_timing_from(start);
+        for i in 1..4 {
+            s.spawn(async move {
+                sleep(Duration::from_millis(20 * i)).await;
+                i
+            });
+        }
+        let timed: Vec<_> = StreamExt::collect(&mut s).await;
+        assert_eq!(timed.len(), 3);
+        for (elapsed, i) in timed {
+            assert!(elapsed >= Duration::from_millis(20 * i), "{:?} for {}", elapsed, i);
+            assert!(elapsed < Duration::from_secs(1));
+        }
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_indexing() {
+    use futures::StreamExt;
+
+    let mut s = unsafe { crate::Scope::create() }.with_indexing();
+    for i in 0..10u64 {
+        // Later futures complete earlier
+        s.spawn(async move {
+            sleep(Duration::from_millis(10 * (10 - i))).await;
+            i * i
+        });
+    }
+
+    let vals: Vec<_> = s.by_ref().collect().await;
+    assert_eq!(vals.len(), 10);
+    assert_eq!(vals[0], (9, 81));
+    for (index, val) in vals {
+        assert_eq!(val, (index * index) as u64);
+    }
+}
+
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn local_scope_and_collect() {
+    use std::rc::Rc;
+
+    let run = async {
+        let not_copy = String::from("hello world!");
+        let not_copy_ref = &not_copy;
+
+        let (_, mut vals) = unsafe { crate::local_scope_and_collect(|s| {
+            for i in 0..10 {
+                // Not `Send`
+                let rc = Rc::new(i);
+                s.spawn_local(async move {
+                    sleep(Duration::from_millis(10)).await;
+                    not_copy_ref.len() + *rc
+                });
+            }
+        }) }.await;
+
+        vals.sort();
+        assert_eq!(vals, (12..22).collect::<Vec<_>>());
+    };
+
+    #[cfg(not(feature = "tokio"))]
+    run.await;
+    #[cfg(feature = "tokio")]
+    tokio::task::LocalSet::new().run_until(run).await;
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scoped_macro() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let mut vals = scoped_blocking!(|s| {
+        for i in 0..10 {
+            s.spawn(async move { not_copy_ref.len() + i });
+        }
+    });
+
+    vals.sort();
+    assert_eq!(vals, (12..22).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_timeout() {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let mut s = unsafe { crate::Scope::create() }.with_indexing();
+    s.spawn_timeout(Duration::from_millis(100), async { 1 });
+    s.spawn_timeout(Duration::from_millis(100), async {
+        sleep(Duration::from_secs(10)).await;
+        2
+    });
+
+    use futures::StreamExt;
+    let mut vals: Vec<_> = s.by_ref().collect().await;
+    vals.sort_by_key(|(index, _)| *index);
+    assert_eq!(vals, vec![(0, Ok(1)), (1, Err(crate::Elapsed::new()))]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_timeout_with_reason() {
+    use crate::CancelReason;
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let mut s = unsafe { crate::Scope::create() }.with_indexing();
+    s.spawn_timeout_with_reason(Duration::from_millis(100), async { None }, Some);
+    s.spawn_timeout_with_reason(Duration::from_millis(100), async {
+        sleep(Duration::from_secs(10)).await;
+        None
+    }, Some);
+
+    use futures::StreamExt;
+    let mut vals: Vec<_> = s.by_ref().collect().await;
+    vals.sort_by_key(|(index, _)| *index);
+    assert_eq!(vals, vec![(0, None), (1, Some(CancelReason::Timeout))]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn on_complete() {
+    use std::sync::{Arc, Mutex};
+
+    let completed = Arc::new(Mutex::new(vec![]));
+    let mut s = unsafe { crate::Scope::create() };
+    {
+        let completed = completed.clone();
+        s.on_complete(move |index, elapsed| {
+            completed.lock().unwrap().push((index, elapsed));
+        });
+    }
+    for i in 0..5u64 {
+        s.spawn(async move {
+            sleep(Duration::from_millis(10 * i)).await;
+        });
+    }
+    assert_eq!(s.collect().await.len(), 5);
+
+    let mut completed = completed.lock().unwrap().clone();
+    completed.sort();
+    assert_eq!(completed.len(), 5);
+    for (i, (index, elapsed)) in completed.into_iter().enumerate() {
+        assert_eq!(index, i);
+        assert!(elapsed >= Duration::from_millis(10 * i as u64));
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn tracing_spans() {
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tracing::{span, Event, Metadata, field::{Field, Visit}};
+
+    /// Records the `scope.task_id` of the spans created,
+    /// and the number of times they are entered.
+    #[derive(Default)]
+    struct Recorder {
+        next_id: AtomicU64,
+        task_ids: Mutex<Vec<(u64, u64)>>,
+        entered: AtomicU64,
+    }
+
+    struct TaskId(Option<u64>);
+    impl Visit for TaskId {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "scope.task_id" { self.0 = Some(value); }
+        }
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata) -> bool { true }
+        fn new_span(&self, attrs: &span::Attributes) -> span::Id {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut task_id = TaskId(None);
+            attrs.record(&mut task_id);
+            if let Some(task_id) = task_id.0 {
+                assert_eq!(attrs.metadata().name(), "scope.task");
+                self.task_ids.lock().unwrap().push((id, task_id));
+            }
+            span::Id::from_u64(id)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event) {}
+        fn enter(&self, _: &span::Id) { self.entered.fetch_add(1, Ordering::SeqCst); }
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let recorder = std::sync::Arc::new(Recorder::default());
+    tracing::subscriber::set_global_default(recorder.clone()).unwrap();
+
+    let mut s = unsafe { crate::Scope::create() }.with_span_name("worker");
+    for _ in 0..5 {
+        s.spawn(async { sleep(Duration::from_millis(10)).await });
+    }
+    assert_eq!(s.collect().await.len(), 5);
+
+    let mut task_ids: Vec<_> = recorder.task_ids.lock().unwrap()
+        .iter().map(|(_, task_id)| *task_id).collect();
+    task_ids.sort();
+    assert_eq!(task_ids, vec![0, 1, 2, 3, 4]);
+    // Entered on each poll: at least before and after the sleep
+    assert!(recorder.entered.load(Ordering::SeqCst) >= 10);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_with_cancel() {
+    use futures::channel::oneshot;
+    use futures::FutureExt;
+
+    let (shutdown, signal) = oneshot::channel::<()>();
+    let signal = signal.shared();
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_with_cancel(async { 1 }, signal.clone().map(|_| 0));
+    for _ in 0..2 {
+        s.spawn_with_cancel(async {
+            sleep(Duration::from_secs(10)).await;
+            2
+        }, signal.clone().map(|_| 0));
+    }
+
+    assert_eq!(s.join_next().await, Some(1));
+    shutdown.send(()).unwrap();
+    let vals = timeout(Duration::from_secs(5), Box::pin(s.collect())).await;
+    assert_eq!(vals, Some(vec![0, 0]));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_map() {
+    let spawn_all = |s: &mut crate::Scope<'_, usize>| {
+        for i in 0..6 {
+            // The futures spawned last complete first.
+            s.spawn(async move {
+                sleep(Duration::from_millis(5 * (6 - i) as u64)).await;
+                i
+            });
+        }
+    };
+
+    let ((), map) = unsafe {
+        crate::scope_and_collect_map(spawn_all, |_, output| output % 2)
+    }.await;
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&0], 4);
+    assert_eq!(map[&1], 5);
+
+    let ((), groups) = unsafe {
+        crate::scope_and_collect_grouped(spawn_all, |index, _| index / 3)
+    }.await;
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[&0], [0, 1, 2]);
+    assert_eq!(groups[&1], [3, 4, 5]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_results() {
+    let (_, (mut successes, mut failures)) = unsafe { crate::scope_and_collect_results(|s| {
+        for i in 0..10 {
+            s.spawn(async move {
+                sleep(Duration::from_millis(10 * (10 - i))).await;
+                if i % 3 == 0 { Err(format!("failed {}", i)) } else { Ok(i) }
+            });
+        }
+    }) }.await;
+
+    successes.sort();
+    failures.sort();
+    assert_eq!(successes, vec![1, 2, 4, 5, 7, 8]);
+    assert_eq!(failures, vec![
+        (0, "failed 0".into()), (3, "failed 3".into()),
+        (6, "failed 6".into()), (9, "failed 9".into()),
+    ]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drain() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let count = AtomicUsize::new(0);
+    let count_ref = &count;
+
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..10 {
+        s.spawn(async move {
+            sleep(Duration::from_millis(10)).await;
+            count_ref.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    s.drain().await;
+    assert_eq!(s.remaining(), 0);
+    assert_eq!(count.load(Ordering::SeqCst), 10);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn remaining_and_total_spawned() {
+    let mut s = unsafe { crate::Scope::create() };
+    assert!(s.is_empty());
+    assert_eq!(s.total_spawned(), 0);
+
+    for i in 0..3 {
+        s.spawn(async move { i });
+    }
+    // Spawned but not yet polled
+    assert_eq!(s.remaining(), 3);
+    assert_eq!(s.total_spawned(), 3);
+    assert!(!s.is_empty());
+
+    s.handle().spawn(async { 3 });
+    assert_eq!(s.remaining(), 4);
+    assert_eq!(s.total_spawned(), 4);
+
+    for n in (0..4).rev() {
+        assert!(s.join_next().await.is_some());
+        assert_eq!(s.remaining(), n);
+    }
+    assert!(s.is_empty());
+    assert_eq!(s.join_next().await, None);
+    assert_eq!(s.total_spawned(), 4);
+    assert_eq!(s.len(), 4);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_all() {
+    let words: Vec<String> = ["hello", "scoped", "world"].iter().map(|w| w.to_string()).collect();
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_all(words.iter().map(|word| async move { word.len() }));
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, vec![5, 5, 6]);
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(2);
+    s.spawn_all_limited((0..10).map(|i| async move {
+        sleep(Duration::from_millis(10)).await;
+        i
+    })).await;
+    assert!(s.in_flight() <= 2);
+    assert_eq!(s.collect().await.len(), 10);
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn create_deterministic() {
+    fn run(seed: u64) -> Vec<usize> {
+        let mut s = unsafe { crate::Scope::create_deterministic(seed) };
+        for i in 0..20 {
+            s.spawn(async move {
+                // Yield a varying number of times
+                for _ in 0..(i % 4) {
+                    let mut yielded = false;
+                    futures::future::poll_fn(|cx| {
+                        if yielded { return std::task::Poll::Ready(()); }
+                        yielded = true;
+                        cx.waker().wake_by_ref();
+                        std::task::Poll::Pending
+                    }).await;
+                }
+                i
+            });
+        }
+        futures::executor::block_on(s.collect())
+    }
+
+    let order = run(42);
+    assert_eq!(order.len(), 20);
+    for _ in 0..5 {
+        assert_eq!(run(42), order);
+    }
+    assert_ne!(run(7), order);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_for_each_concurrent() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let weights = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let weights_ref = &weights;
+    let total = AtomicUsize::new(0);
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    let (total, running, max_running) = (&total, &running, &max_running);
+
+    unsafe { crate::scope_for_each_concurrent(3, 0..weights.len(), |i| async move {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        sleep(Duration::from_millis(10)).await;
+        total.fetch_add(weights_ref[i], Ordering::SeqCst);
+        running.fetch_sub(1, Ordering::SeqCst);
+    }) }.await;
+
+    assert_eq!(total.load(Ordering::SeqCst), 36);
+    assert!(max_running.load(Ordering::SeqCst) <= 3);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_buffer_unordered() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let weights = [1, 2, 3, 4, 5, 6, 7, 8];
+    let weights_ref = &weights;
+    let created = AtomicUsize::new(0);
+    let yielded = AtomicUsize::new(0);
+    let (created_ref, yielded_ref) = (&created, &yielded);
+
+    let fns = (0..weights.len()).map(|i| move || {
+        // Created only once there is room for it
+        let now = created_ref.fetch_add(1, Ordering::SeqCst) + 1;
+        assert!(now - yielded_ref.load(Ordering::SeqCst) <= 3);
+        async move {
+            sleep(Duration::from_millis(10)).await;
+            weights_ref[i]
+        }
+    });
+    let mut total = 0;
+    unsafe { crate::scope_buffer_unordered(3, fns, |weight| {
+        yielded.fetch_add(1, Ordering::SeqCst);
+        total += weight;
+    }) }.await;
+
+    assert_eq!(total, 36);
+    assert_eq!(created.load(Ordering::SeqCst), 8);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drop_policy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::DropPolicy;
+
+    for (policy, expected) in [(DropPolicy::CancelAll, 1), (DropPolicy::BlockOnFinish, 2)] {
+        let finished = AtomicUsize::new(0);
+        let finished_ref = &finished;
+
+        let mut s = unsafe { crate::Scope::create() }.with_drop_policy(policy);
+        assert_eq!(s.drop_policy(), policy);
+        s.spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        s.spawn_cancellable(async move {
+            sleep(Duration::from_millis(50)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        }, || ());
+        std::mem::drop(s);
+
+        assert_eq!(finished.load(Ordering::SeqCst), expected);
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drop_policy_abort_all() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::DropPolicy;
+
+    let finished = AtomicUsize::new(0);
+    let finished_ref = &finished;
+    let (mut s, _) = unsafe { crate::scope(|s| {
+        s.spawn(async move {
+            sleep(Duration::from_secs(10)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        });
+        s.spawn_cancellable(async move {
+            sleep(Duration::from_secs(10)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        }, || ());
+    }) };
+    s = s.with_drop_policy(DropPolicy::AbortAll);
+    s.spawn(async { finished_ref.fetch_add(1, Ordering::SeqCst); });
+    sleep(Duration::from_millis(20)).await;
+
+    let start = std::time::Instant::now();
+    std::mem::drop(s);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn poll_next() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Sums the outputs of a scope.
+    struct Sum<'s, 'a>(&'s mut crate::Scope<'a, usize>, usize);
+
+    impl<'s, 'a> Future for Sum<'s, 'a> {
+        type Output = usize;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<usize> {
+            loop {
+                match Pin::new(&mut *self.0).poll_next(cx) {
+                    Poll::Ready(Some(val)) => self.1 += val,
+                    Poll::Ready(None) => return Poll::Ready(self.1),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }
+    }
+
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..10 {
+        s.spawn(async move { i });
+    }
+    assert_eq!(Sum(&mut s, 0).await, 45);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancel_all() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the connections released on drop.
+    struct Connection<'a>(&'a AtomicUsize);
+    impl<'a> Drop for Connection<'a> {
+        fn drop(&mut self) {
+            std::thread::sleep(Duration::from_millis(20));
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let released = AtomicUsize::new(0);
+    let released_ref = &released;
+
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..5 {
+        s.spawn_cancellable(async move {
+            let _conn = Connection(released_ref);
+            sleep(Duration::from_secs(10)).await;
+        }, || ());
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    s.cancel_all().await;
+    assert_eq!(released.load(Ordering::SeqCst), 5);
+    assert_eq!(s.collect().await.len(), 5);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_join_errors() {
+    let mut s = unsafe { crate::Scope::create() }.with_join_errors();
+    s.spawn(async { 1 });
+    s.spawn(async { panic!("oops") });
+    let slow = s.spawn(async {
+        sleep(Duration::from_secs(10)).await;
+        3
+    });
+    slow.abort();
+    std::mem::drop(slow);
+
+    let (mut vals, mut panics, mut cancelled) = (vec![], vec![], vec![]);
+    while let Some(result) = s.join_next().await {
+        match result {
+            Ok(val) => vals.push(val),
+            Err(err) if err.is_panic() => {
+                let caught = err.try_into_panic().unwrap();
+                assert_eq!(caught.message(), Some("oops"));
+                panics.push(caught.index());
+            },
+            Err(err) => {
+                assert!(err.is_cancelled());
+                cancelled.push(err.index());
+            },
+        }
+    }
+    assert_eq!(vals, vec![1]);
+    assert_eq!(panics, vec![Some(1)]);
+    assert_eq!(cancelled, vec![Some(2)]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_max_depth() {
+    use crate::{SpawnHandle, DepthExceeded};
+
+    /// Crawls a binary tree of pages, until the depth is
+    /// exhausted; yields the depth of each page.
+    fn crawl<'a>(h: &SpawnHandle<'a, Result<usize, DepthExceeded>>) {
+        let spawned = h.spawn_nested(|child| async move {
+            crawl(&child);
+            crawl(&child);
+            Ok(child.depth())
+        });
+        if let Err(err) = spawned {
+            h.spawn(async move { Err(err) });
+        }
+    }
+
+    let mut s = unsafe { crate::Scope::create() }.with_max_depth(3);
+    crawl(&s.handle());
+    let vals = s.collect().await;
+
+    let mut depths: Vec<_> = vals.iter().filter_map(|val| val.ok()).collect();
+    depths.sort();
+    assert_eq!(depths, vec![1, 2, 2, 3, 3, 3, 3]);
+    let errors = vals.iter().filter(|val| val.is_err()).count();
+    assert_eq!(errors, 8);
+    assert_eq!(vals[vals.len() - 1].err().map_or(3, |err| err.max_depth), 3);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_pinned() {
+    let payload = [7u8; 4096];
+    let (_, vals) = unsafe { crate::scope_and_collect(|s| {
+        for i in 0..10 {
+            let payload = &payload;
+            s.spawn_pinned(Box::pin(async move {
+                let buf = [i as u8; 1024];
+                sleep(Duration::from_millis(1)).await;
+                buf.len() + payload[i] as usize
+            }));
+        }
+    }) }.await;
+    assert_eq!(vals, vec![1031; 10]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn pause_and_resume() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let resumed = AtomicBool::new(false);
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { 1 });
+    s.pause();
+    assert!(s.is_paused());
+
+    // The stream is still drained while paused.
+    assert_eq!(s.join_next().await, Some(1));
+
+    let handle = s.handle();
+    let resumed = &resumed;
+    s.spawn(async move {
+        sleep(Duration::from_millis(50)).await;
+        resumed.store(true, Ordering::SeqCst);
+        handle.resume();
+        2
+    });
+    s.spawn_limited(async { 3 }).await;
+    assert!(resumed.load(Ordering::SeqCst));
+    assert!(!s.is_paused());
+
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, vec![2, 3]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn resume_wakes_all() {
+    use futures::stream::FuturesUnordered;
+
+    let s = unsafe { crate::Scope::<usize>::create() };
+    s.pause();
+
+    // Several producers wait on the paused scope, each with
+    // a waker of its own: resuming must wake all of them.
+    let handle = s.handle();
+    let producers: FuturesUnordered<_> = (0..3).map(|_| s.reserve(1)).collect();
+    let resume = async move {
+        sleep(Duration::from_millis(10)).await;
+        handle.resume();
+    };
+    let (permits, ()) = timeout(Duration::from_secs(1), Box::pin(
+        futures::future::join(futures::StreamExt::collect::<Vec<_>>(producers), resume)
+    )).await.expect("all producers should be resumed");
+    assert_eq!(permits.len(), 3);
+    assert!(!s.is_paused());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_n() {
+    let delays = [30u64, 10, 1000, 20, 1000];
+    let (_, mut vals) = unsafe { crate::scope_and_collect_n(3, |s| {
+        for &delay in &delays {
+            s.spawn_cancellable(async move {
+                sleep(Duration::from_millis(delay)).await;
+                delay
+            }, || 0);
+        }
+    }) }.await;
+    vals.sort();
+    assert_eq!(vals, vec![10, 20, 30]);
+
+    let (_, vals) = unsafe { crate::scope_and_collect_n(3, |s| {
+        s.spawn(async { 1 });
+    }) }.await;
+    assert_eq!(vals, vec![1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn into_stream() {
+    let data: Vec<usize> = (0..5).collect();
+    let spawn_all = |s: &mut crate::Scope<'_, usize>| {
+        for &i in &data {
+            s.spawn(async move {
+                sleep(Duration::from_millis(10 * (5 - i as u64))).await;
+                i
+            });
+        }
+    };
+
+    let mut s = unsafe { crate::Scope::create() };
+    spawn_all(&mut s);
+    let mut stream = s.into_stream();
+    assert_eq!(stream.remaining(), 5);
+    assert_eq!(stream.collect_ordered().await, data);
+
+    let mut s = unsafe { crate::Scope::create() };
+    spawn_all(&mut s);
+    let batches = s.into_stream().buffered_collect(2).await;
+    assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+    let mut vals: Vec<_> = batches.into_iter().flatten().collect();
+    vals.sort();
+    assert_eq!(vals, data);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_logged() {
+    let names = ["a", "b", "c"];
+    let names_ref = &names;
+    let ((), (mut vals, logs)) = unsafe {
+        crate::scope_and_collect_logged(|s| {
+            for (i, name) in names_ref.iter().enumerate() {
+                s.spawn_logged(move |logger| async move {
+                    logger.log(format!("start {}", name));
+                    sleep(Duration::from_millis(5 * i as u64)).await;
+                    logger.log("done");
+                    i
+                });
+            }
+            s.spawn(async { 3 });
+        })
+    }.await;
+    vals.sort();
+    assert_eq!(vals, [0, 1, 2, 3]);
+    assert_eq!(logs.len(), 6);
+    for (i, name) in names.iter().enumerate() {
+        let lines: Vec<_> = logs.iter()
+            .filter(|line| line.index == i)
+            .map(|line| line.message.as_str())
+            .collect();
+        assert_eq!(lines, [format!("start {}", name).as_str(), "done"]);
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_with_progress() {
+    use futures::StreamExt;
+
+    let steps = 4;
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..2 {
+        let steps = &steps;
+        s.spawn_with_progress(|tx| async move {
+            for step in 1..=*steps {
+                sleep(Duration::from_millis(5)).await;
+                tx.send(100. * step as f64 / *steps as f64);
+            }
+            *steps
+        });
+    }
+    let events = s.progress_events();
+    assert_eq!(crate::Scope::collect(&mut s).await, vec![4, 4]);
+    std::mem::drop(s);
+
+    let events: Vec<_> = events.collect().await;
+    assert_eq!(events.len(), 8);
+    for index in 0..2 {
+        let values: Vec<_> = events.iter()
+            .filter(|event| event.index == index)
+            .map(|event| event.value)
+            .collect();
+        assert_eq!(values, vec![25., 50., 75., 100.]);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn create_with_handle() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_time()
+        .build()
+        .unwrap();
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    // This thread is not within the runtime.
+    let mut s = unsafe { crate::Scope::create_with_handle(runtime.handle().clone()) };
+    for i in 0..10 {
+        s.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            not_copy_ref.len() + i
+        });
+    }
+    s.spawn_blocking(move || not_copy_ref.len());
+    let mut vals = futures::executor::block_on(s.collect());
+    vals.sort();
+    assert_eq!(vals, vec![12, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn remaining_cancellable() {
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..2 {
+        s.spawn(async {
+            sleep(Duration::from_millis(50)).await;
+            1
+        });
+    }
+    for _ in 0..3 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            2
+        }, || 0);
+    }
+    assert_eq!(s.remaining_cancellable(), 3);
+    assert_eq!(s.remaining_uncancellable(), 2);
+
+    s.cancel_all().await;
+    assert_eq!(s.remaining_cancellable(), 0);
+    assert_eq!(s.remaining_uncancellable(), 5);
+
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, vec![0, 0, 0, 1, 1]);
+    assert_eq!(s.remaining_cancellable() + s.remaining_uncancellable(), 0);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_without_cancellation() {
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..3 {
+        s.spawn(async move {
+            sleep(Duration::from_millis(200)).await;
+            i
+        });
+    }
+    assert_eq!(s.remaining_cancellable(), 0);
+    assert_eq!(s.remaining_uncancellable(), 3);
+
+    // There are no cancellable futures to wait for, and
+    // the others run to completion.
+    assert!(timeout(Duration::from_millis(100), Box::pin(s.cancel_all())).await.is_some());
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [0, 1, 2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancel_before_cancellable_spawn() {
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { 1 });
+    s.cancel().await;
+
+    // The futures spawned cancellable after the scope was
+    // cancelled are cancelled too, via the scope or a
+    // handle.
+    s.spawn_cancellable(async {
+        sleep(Duration::from_secs(10)).await;
+        2
+    }, || 0);
+    s.handle().spawn_cancellable(async {
+        sleep(Duration::from_secs(10)).await;
+        3
+    }, || 0);
+    let mut vals = timeout(Duration::from_secs(1), Box::pin(s.collect())).await.unwrap();
+    vals.sort();
+    assert_eq!(vals, [0, 0, 1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_ordered_window() {
+    let mut s = unsafe { crate::Scope::create() }.with_ordered_window(2);
+    assert!(s.is_ordered());
+    for i in 0..8u64 {
+        s.spawn(async move {
+            sleep(Duration::from_millis(5 * ((i * 3) % 8))).await;
+            i
+        });
+    }
+    assert_eq!(s.collect().await, (0..8).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn ordered_window_bounds_in_flight() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let running = AtomicUsize::new(0);
+    let max = AtomicUsize::new(0);
+    let mut s = unsafe { crate::Scope::create() }.with_ordered_window(2);
+    for i in 0..8u64 {
+        let (running, max) = (&running, &max);
+        s.spawn(async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(5 * ((i * 3) % 8))).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            i
+        });
+    }
+    assert_eq!(s.remaining(), 8);
+    sleep(Duration::from_millis(50)).await;
+    assert!(max.load(Ordering::SeqCst) <= 2);
+    assert_eq!(s.collect().await, (0..8).collect::<Vec<_>>());
+    assert!(max.load(Ordering::SeqCst) <= 2);
+
+    // The futures held back are cancelled too
+    let mut s = unsafe { crate::Scope::create() }.with_ordered_window(1);
+    for _ in 0..3 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            1
+        }, || 0);
+    }
+    assert!(timeout(Duration::from_secs(1), Box::pin(s.cancel_all())).await.is_some());
+    assert_eq!(s.collect().await, vec![0, 0, 0]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_outcome_tagging() {
+    use crate::TaskOutcome;
+
+    let mut s = unsafe { crate::Scope::create() }.with_outcome_tagging();
+    s.spawn(async { 1 });
+    s.spawn_cancellable(async { 2 }, || 0);
+    for _ in 0..3 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            3
+        }, || 0);
+    }
+    let mut vals = vec![s.join_next().await.unwrap(), s.join_next().await.unwrap()];
+    s.cancel().await;
+    vals.extend(s.collect().await);
+    vals.sort_by_key(|outcome| outcome.is_cancelled());
+
+    assert_eq!(vals.iter().filter(|outcome| outcome.is_cancelled()).count(), 3);
+    assert!(vals[2..].iter().all(|&outcome| outcome == TaskOutcome::Cancelled(0)));
+    let mut completed: Vec<_> = vals.into_iter().filter_map(TaskOutcome::completed).collect();
+    completed.sort();
+    assert_eq!(completed, vec![1, 2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_rate_limit() {
+    use std::time::Instant;
+
+    let mut s = unsafe { crate::Scope::create() }
+        .with_concurrency_limit(100)
+        .with_rate_limit(50.);
+    assert_eq!(s.rate_limit(), Some(50.));
+
+    // The first 50 spawns are a burst, and the next 10 are
+    // spaced 20ms apart.
+    let start = Instant::now();
+    s.spawn_all_limited((0..60).map(|i| async move { i })).await;
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    s.set_rate_limit(1000.);
+    assert_eq!(s.rate_limit(), Some(1000.));
+    s.spawn_limited(async { 60 }).await;
+
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, (0..61).collect::<Vec<_>>());
+
+    // The wait for a token overflows a `Duration`, and is
+    // capped instead.
+    let mut s = unsafe { crate::Scope::create() }.with_rate_limit(1e-300);
+    s.spawn_limited(async { 0 }).await;
+    assert!(timeout(Duration::from_millis(20), Box::pin(s.spawn_limited(async { 1 }))).await.is_none());
+    assert_eq!(s.collect().await, [0]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_timeout() {
+    let (_, partial) = unsafe { crate::scope_and_collect_timeout(Duration::from_millis(50), |s| {
+        s.spawn(async { 1 });
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            2
+        }, || 0);
+        s.spawn(async {
+            sleep(Duration::from_millis(100)).await;
+            3
+        });
+    }) }.await;
+    assert_eq!(partial.outputs, vec![1]);
+    assert!(partial.timed_out);
+    assert_eq!(partial.still_running, 1);
+
+    let (_, partial) = unsafe { crate::scope_and_collect_timeout(Duration::from_secs(10), |s| {
+        s.spawn(async { 1 });
+    }) }.await;
+    assert_eq!(partial.outputs, vec![1]);
+    assert!(!partial.timed_out);
+    assert_eq!(partial.still_running, 0);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_abortable() {
+    let mut s = unsafe { crate::Scope::create() }.with_indexing();
+    let handles: Vec<_> = (0..5).map(|i| s.spawn_abortable(async move {
+        sleep(Duration::from_millis(50)).await;
+        i
+    })).collect();
+    handles[3].abort();
+
+    let mut vals: Vec<_> = futures::StreamExt::collect(s).await;
+    vals.sort_by_key(|(index, _)| *index);
+    for (index, val) in vals {
+        if index == 3 {
+            assert_eq!(val, Err(futures::future::Aborted));
+        } else {
+            assert_eq!(val, Ok(index));
+        }
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_abort_on_panic() {
+    use std::panic::AssertUnwindSafe;
+    use std::time::Instant;
+    use futures::FutureExt;
+
+    let start = Instant::now();
+    let mut s = unsafe { crate::Scope::create() }.with_abort_on_panic(true);
+    s.spawn_cancellable(async {
+        sleep(Duration::from_secs(10)).await;
+        1
+    }, || 0);
+    s.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        panic!("mid-batch failure");
+    });
+    s.spawn(async { 2 });
+
+    let caught = AssertUnwindSafe(s.collect()).catch_unwind().await;
+    let payload = caught.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"mid-batch failure"));
+
+    // The sibling was cancelled, rather than waited for.
+    let vals = s.collect().await;
+    assert!(vals.contains(&0));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_mapped() {
+    #[derive(Debug, PartialEq)]
+    enum Output {
+        Name(String),
+        Count(u32),
+    }
+
+    let words = ["hello", "world"];
+    let (_, vals) = unsafe { crate::scope_and_collect_ordered(|s| {
+        s.spawn_mapped(async { words.join(" ") }, Output::Name);
+        s.spawn_mapped(async {
+            sleep(Duration::from_millis(10)).await;
+            words.len() as u32
+        }, Output::Count);
+    }) }.await;
+    assert_eq!(vals, vec![Output::Name("hello world".into()), Output::Count(2)]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_blocking_cancellable() {
+    use std::sync::mpsc;
+
+    let (started_tx, started_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let release_rx = std::sync::Mutex::new(release_rx);
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_blocking_cancellable(|| {
+        started_tx.send(()).unwrap();
+        release_rx.lock().unwrap().recv().unwrap();
+        1
+    }, || 0);
+    started_rx.recv().unwrap();
+
+    // The started closure runs to completion, while the
+    // cancelled future yields its fallback.
+    s.cancel().await;
+    s.spawn_blocking_cancellable(|| 2, || 0);
+    release_tx.send(()).unwrap();
+
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, vec![0, 1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drain_ready() {
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    let mut s = unsafe { crate::Scope::create() };
+    let mut vals = Vec::new();
+    assert_eq!(s.drain_ready(&mut vals), 0);
+
+    s.spawn(async move { rx.await.unwrap() });
+    for i in 0..3 {
+        s.spawn(async move { i });
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(s.drain_ready(&mut vals), 3);
+    vals.sort();
+    assert_eq!(vals, vec![0, 1, 2]);
+    assert_eq!(s.drain_ready(&mut vals), 0);
+
+    tx.send(3).unwrap();
+    assert_eq!(s.collect().await, vec![3]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn reuse_after_drain() {
+    let batches = [vec![1, 2, 3], vec![4, 5]];
+
+    let mut s = unsafe { crate::Scope::create() };
+    for batch in &batches {
+        for val in batch {
+            s.spawn(async move { *val });
+        }
+        let mut vals = s.collect().await;
+        vals.sort();
+        assert_eq!(&vals, batch);
+        assert!(s.is_empty());
+        assert_eq!(s.join_next().await, None);
+    }
+    assert_eq!(s.total_spawned(), 5);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_until_memory() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let weight = AtomicUsize::new(0);
+    let peak = AtomicUsize::new(0);
+    let weight = &weight;
+    let peak = &peak;
+
+    // The limit is far above any resident set size, hence
+    // only the weight is bounded.
+    let limit = usize::MAX / 2;
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..6 {
+        s.spawn_until_memory(limit, limit / 3 + 1, async move {
+            let current = weight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(current, Ordering::SeqCst);
+            sleep(Duration::from_millis(20)).await;
+            weight.fetch_sub(1, Ordering::SeqCst);
+        }).await;
+    }
+    s.collect().await;
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "memory-stats")]
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_until_memory_rss() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let running = AtomicUsize::new(0);
+    let peak = AtomicUsize::new(0);
+    let running = &running;
+    let peak = &peak;
+
+    // Any resident set size exceeds the limit: the futures
+    // are driven one at a time.
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..3 {
+        s.spawn_until_memory(1, 0, async move {
+            let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(current, Ordering::SeqCst);
+            sleep(Duration::from_millis(20)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        }).await;
+    }
+    s.collect().await;
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn select_scope() {
+    let start = std::time::Instant::now();
+    let (_, val) = unsafe { crate::select_scope(|s| {
+        for &delay in &[1000u64, 10] {
+            s.spawn_cancellable(async move {
+                sleep(Duration::from_millis(delay)).await;
+                delay
+            }, || 0);
+        }
+    }) }.await;
+    assert_eq!(val, Some(10));
+    // The slow future was cancelled.
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    // The slow future is not cancellable, and is aborted.
+    let start = std::time::Instant::now();
+    let (_, val) = unsafe { crate::select_scope(|s| {
+        for &delay in &[2000u64, 10] {
+            s.spawn(async move {
+                sleep(Duration::from_millis(delay)).await;
+                delay
+            });
+        }
+    }) }.await;
+    assert_eq!(val, Some(10));
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    let (_, val) = unsafe { crate::select_scope(|_: &mut crate::Scope<'_, u64>| {}) }.await;
+    assert_eq!(val, None);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_assert_ordered() {
+    use futures::{FutureExt, StreamExt};
+
+    let spawn_all = |s: &mut crate::Scope<'_, u64>| {
+        for i in 0..5u64 {
+            // Later futures complete earlier
+            s.spawn(async move {
+                sleep(Duration::from_millis(10 * (5 - i))).await;
+                i
+            });
+        }
+    };
+
+    let mut s = unsafe { crate::Scope::create() }.with_ordering();
+    spawn_all(&mut s);
+    let s = s.with_indexing().with_assert_ordered(true);
+    let vals: Vec<_> = s.map(|(_, val)| val).collect().await;
+    assert_eq!(vals, vec![0, 1, 2, 3, 4]);
+
+    let mut s = unsafe { crate::Scope::create() };
+    spawn_all(&mut s);
+    let s = s.with_indexing().with_assert_ordered(true);
+    let result = std::panic::AssertUnwindSafe(s.collect::<Vec<_>>()).catch_unwind().await;
+    assert_eq!(result.is_err(), cfg!(debug_assertions));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_named() {
+    let mut s = unsafe { crate::Scope::create() };
+    let handle = s.spawn_named("answer", async { 42 });
+    s.spawn_named(String::from("other"), async { 1 });
+    assert_eq!(handle.await, 42);
+    assert_eq!(s.collect().await, vec![1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_initial_capacity() {
+    let mut s = unsafe { crate::Scope::create() }
+        .with_ordered_window(4)
+        .with_initial_capacity(100);
+    for i in 0..100 {
+        s.spawn(async move { i });
+    }
+    assert_eq!(s.collect().await, (0..100).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_coop_budget() {
+    let mut s = unsafe { crate::Scope::create() }.with_coop_budget(3);
+    for i in 0..8 {
+        s.spawn(async move { i });
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    // The stream yields control after every three outputs.
+    let mut vals = Vec::new();
+    assert_eq!(s.drain_ready(&mut vals), 3);
+    assert_eq!(s.drain_ready(&mut vals), 3);
+    vals.extend(s.collect().await);
+    vals.sort();
+    assert_eq!(vals, (0..8).collect::<Vec<_>>());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_collect_until() {
+    let delays = [10u64, 1000, 20, 30, 1000];
+    let spawn_all = |s: &mut crate::Scope<'_, u64>| {
+        for &delay in &delays {
+            s.spawn_cancellable(async move {
+                sleep(Duration::from_millis(delay)).await;
+                delay
+            }, || 0);
+        }
+    };
+
+    let (_, vals) = unsafe { crate::scope_and_collect_until(spawn_all, |&delay| delay >= 20) }.await;
+    assert_eq!(vals, vec![10, 20]);
+
+    // Without a match, all the outputs are collected.
+    let (_, mut vals) = unsafe { crate::scope_and_collect_until(|s| {
+        for i in 0..3 {
+            s.spawn(async move { i });
+        }
+    }, |_| false) }.await;
+    vals.sort();
+    assert_eq!(vals, vec![0, 1, 2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_fn() {
+    let not_copy = String::from("hello world!");
+    let not_copy_ref = &not_copy;
+
+    let (_, vals) = unsafe { crate::scope_and_collect(|s| {
+        for i in 0..5 {
+            s.spawn_fn(|| async move {
+                assert_eq!(not_copy_ref, "hello world!");
+                i
+            });
+        }
+    }) }.await;
+    assert_eq!(vals.len(), 5);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drain_timeout() {
+    let (tx, rx) = futures::channel::oneshot::channel::<()>();
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async {});
+    s.spawn(async move { rx.await.unwrap() });
+
+    // The second future is stuck until the sender is used.
+    assert!(!s.drain_timeout(Duration::from_millis(50)).await);
+    assert_eq!(s.remaining(), 1);
+
+    tx.send(()).unwrap();
+    assert!(s.drain_timeout(Duration::from_secs(5)).await);
+    assert!(s.is_empty());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn set_context() {
+    use crate::current_context;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TraceId(u64);
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { current_context::<TraceId>() });
+    s.set_context(TraceId(7));
+    for _ in 0..3 {
+        s.spawn(async {
+            sleep(Duration::from_millis(10)).await;
+            // Other types are not the context.
+            assert_eq!(current_context::<u64>(), None);
+            current_context::<TraceId>()
+        });
+    }
+    assert_eq!(current_context::<TraceId>(), None);
+
+    let vals = s.collect().await;
+    assert_eq!(vals.iter().filter(|val| val.is_none()).count(), 1);
+    assert_eq!(vals.iter().filter(|val| **val == Some(TraceId(7))).count(), 3);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn chunks_ready() {
+    use futures::StreamExt;
+
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..5 {
+        s.spawn(async move { i });
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    let mut vals = Vec::new();
+    let mut chunks = s.chunks_ready(3);
+    // The completed outputs are batched
+    while let Some(chunk) = chunks.next().await {
+        assert!(chunk.len() <= 3);
+        vals.push(chunk);
+    }
+    assert_eq!(vals.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(), vec![3, 2]);
+
+    // Partial batches are yielded without waiting
+    s.spawn(async { 5 });
+    s.spawn(async {
+        sleep(Duration::from_millis(100)).await;
+        6
+    });
+    let mut chunks = s.chunks_ready(3);
+    assert_eq!(chunks.next().await, Some(vec![5]));
+    assert_eq!(chunks.next().await, Some(vec![6]));
+    assert_eq!(chunks.next().await, None);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn abort_handle() {
+    let mut s = unsafe { crate::Scope::create() };
+    let handle = s.abort_handle();
+    for _ in 0..3 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            1
+        }, || 0);
+    }
+
+    // Abort from an unrelated thread
+    let aborter = handle.clone();
+    std::thread::spawn(move || aborter.abort()).join().unwrap();
+    assert!(handle.is_aborted());
+    assert_eq!(timeout(Duration::from_secs(1), Box::pin(s.collect())).await, Some(vec![0, 0, 0]));
+
+    std::mem::drop(s);
+    assert!(!handle.is_aborted());
+    handle.abort();
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn try_spawn() {
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(1);
+    assert!(s.try_spawn(async move { rx.await.unwrap() }).is_ok());
+
+    // At the limit: the future is returned back
+    let shed = s.try_spawn(async { 2 }).err().unwrap();
+    tx.send(1).unwrap();
+    assert_eq!(s.join_next().await, Some(1));
+
+    assert!(s.try_spawn(shed).is_ok());
+    assert_eq!(s.collect().await, vec![2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn reserve() {
+    let (tx1, rx1) = futures::channel::oneshot::channel();
+    let (tx2, rx2) = futures::channel::oneshot::channel();
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(3);
+    let permits = s.reserve(2).await;
+    assert_eq!(permits.remaining(), 2);
+
+    // One of the limit is left for the other spawns
+    assert!(s.try_spawn(async move { rx1.await.unwrap() }).is_ok());
+    let shed = s.try_spawn(async { 2 }).err().unwrap();
+
+    s.spawn_reserved(&permits, async move { rx2.await.unwrap() });
+    assert_eq!(permits.remaining(), 1);
+    let shed = s.try_spawn(shed).err().unwrap();
+
+    // The unused permit is released on drop
+    std::mem::drop(permits);
+    assert!(s.try_spawn(shed).is_ok());
+    tx1.send(1).unwrap();
+    tx2.send(3).unwrap();
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [1, 2, 3]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn reserve_concurrently() {
+    use futures::stream::FuturesUnordered;
+
+    let (tx1, rx1) = futures::channel::oneshot::channel();
+    let (tx2, rx2) = futures::channel::oneshot::channel();
+
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(2);
+    assert!(s.try_spawn(async move { rx1.await.unwrap() }).is_ok());
+    assert!(s.try_spawn(async move { rx2.await.unwrap() }).is_ok());
+
+    // Two producers wait for the limit at once, each with
+    // a waker of its own: both must be woken.
+    let producers: FuturesUnordered<_> = (0..2).map(|_| s.reserve(1)).collect();
+    let release = async move {
+        sleep(Duration::from_millis(10)).await;
+        tx1.send(1).unwrap();
+        tx2.send(2).unwrap();
+    };
+    let (permits, ()) = timeout(Duration::from_secs(1), Box::pin(
+        futures::future::join(futures::StreamExt::collect::<Vec<_>>(producers), release)
+    )).await.expect("both producers should reserve");
+    assert_eq!(permits.len(), 2);
+
+    std::mem::drop(permits);
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [1, 2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn block_output() {
+    let spawn_all = |s: &mut crate::Scope<'_, usize>| {
+        for i in 0..3 {
+            s.spawn(async move { i });
+        }
+        "spawned"
+    };
+
+    let (mut stream, output) = unsafe { crate::scope(spawn_all) };
+    assert_eq!(output, "spawned");
+    assert_eq!(stream.collect().await.len(), 3);
+
+    let (output, vals) = crate::scope_and_block(spawn_all);
+    assert_eq!((output, vals.len()), ("spawned", 3));
+
+    let (output, vals) = unsafe { crate::scope_and_collect(spawn_all) }.await;
+    assert_eq!((output, vals.len()), ("spawned", 3));
+
+    let (output, vals) = unsafe { crate::scope_and_collect_ordered(spawn_all) }.await;
+    assert_eq!((output, vals), ("spawned", vec![0, 1, 2]));
+
+    let mut vals = Vec::new();
+    let output = unsafe { crate::scope_and_collect_into(spawn_all, &mut vals) }.await;
+    assert_eq!((output, vals.len()), ("spawned", 3));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn peek_ready() {
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    let mut s = unsafe { crate::Scope::create() }.with_ordering();
+    for i in 0..3 {
+        s.spawn(async move { i });
+    }
+    s.spawn(async move { rx.await.unwrap() });
+    sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(s.completed_count(), 3);
+    assert_eq!(s.peek_ready().to_vec(), vec![0, 1, 2]);
+    // Peeking does not consume the outputs
+    assert_eq!(s.peek_ready().to_vec(), vec![0, 1, 2]);
+    assert_eq!(s.remaining(), 4);
+
+    assert_eq!(s.join_next().await, Some(0));
+    assert_eq!(s.peek_ready().to_vec(), vec![1, 2]);
+    tx.send(3).unwrap();
+    assert_eq!(s.collect().await, vec![1, 2, 3]);
+    assert_eq!(s.completed_count(), 4);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_and_process() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let sum = AtomicUsize::new(0);
+    let processing = AtomicUsize::new(0);
+    let (sum, processing) = (&sum, &processing);
+
+    let output = unsafe { crate::scope_and_process(|s| {
+        for i in 0..10 {
+            s.spawn(async move { i });
+        }
+        "spawned"
+    }, |val| async move {
+        // The outputs are processed one at a time
+        assert_eq!(processing.fetch_add(1, Ordering::SeqCst), 0);
+        sleep(Duration::from_millis(1)).await;
+        sum.fetch_add(val, Ordering::SeqCst);
+        processing.fetch_sub(1, Ordering::SeqCst);
+    }) }.await;
+
+    assert_eq!(output, "spawned");
+    assert_eq!(sum.load(Ordering::SeqCst), 45);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn cancellation_token() {
+    use tokio_util::sync::CancellationToken;
+
+    let token = CancellationToken::new();
+    let mut s = unsafe { crate::Scope::create_with_token(token.clone()) };
+    for _ in 0..3 {
+        s.spawn_cancellable(async {
+            sleep(Duration::from_secs(10)).await;
+            1
+        }, || 0);
+    }
+    s.spawn_with_token(|token| async move {
+        token.cancelled().await;
+        2
+    });
+
+    sleep(Duration::from_millis(10)).await;
+    assert!(!s.abort_handle().is_aborted());
+    token.cancel();
+    let mut vals = timeout(Duration::from_secs(1), Box::pin(s.collect())).await
+        .expect("cancelled tasks should be aborted");
+    vals.sort();
+    assert_eq!(vals, [0, 0, 0, 2]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancellable_fallback() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use futures::FutureExt;
+
+    let calls = AtomicUsize::new(0);
+    let lock = &futures::lock::Mutex::new(Vec::new());
+
+    // Completed futures never call their fallback
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..3 {
+        s.spawn_cancellable(async move { i }, || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            0
+        });
+        s.spawn_cancellable_async(async move { i }, |_| async {
+            lock.lock().await.push("cleanup");
+            0
+        });
+    }
+    assert_eq!(s.collect().await.len(), 6);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert!(lock.lock().await.is_empty());
+
+    // Cancelled futures call it exactly once
+    let mut s = unsafe { crate::Scope::create() };
+    for _ in 0..3 {
+        s.spawn_cancellable(sleep(Duration::from_secs(10)).map(|_| 1), || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            0
+        });
+        s.spawn_cancellable_async(sleep(Duration::from_secs(10)).map(|_| 1), |reason| async move {
+            assert_eq!(reason, crate::CancelReason::AbortAll);
+            sleep(Duration::from_millis(5)).await;
+            lock.lock().await.push("cleanup");
+            2
+        });
+    }
+    sleep(Duration::from_millis(10)).await;
+    s.cancel().await;
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [0, 0, 0, 2, 2, 2]);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(lock.lock().await.len(), 3);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancel_reason() {
+    use crate::CancelReason;
+
+    // The outputs of cancelled futures are discarded here
+    let reasons = std::sync::Mutex::new(Vec::new());
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_cancellable_with_reason(async {
+        sleep(Duration::from_secs(10)).await;
+    }, |reason| reasons.lock().unwrap().push(reason));
+    let partial = s.collect_timeout(Duration::from_millis(20)).await;
+    assert!(partial.timed_out);
+    std::mem::drop(s);
+    assert_eq!(*reasons.lock().unwrap(), [CancelReason::Timeout]);
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_cancellable_with_reason(async {
+        sleep(Duration::from_secs(10)).await;
+        None
+    }, Some);
+    s.abort_all();
+    assert_eq!(s.collect().await, [Some(CancelReason::AbortAll)]);
+
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn_cancellable_with_reason(async {
+        sleep(Duration::from_secs(10)).await;
+        None
+    }, Some);
+    s.abort_handle().abort();
+    s.abort_all();
+    assert_eq!(s.collect().await, [Some(CancelReason::ExternalSignal)]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn thread_affinity() {
+    use std::sync::{Arc, Mutex};
+
+    /// Records the workers requested, and spawns anywhere.
+    #[derive(Default, Clone)]
+    struct Recording(Arc<Mutex<Vec<Vec<usize>>>>);
+
+    unsafe impl crate::Spawner for Recording {
+        type Handle<T> = <crate::DefaultSpawner as crate::Spawner>::Handle<T>;
+
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: std::future::Future + Send + 'static, F::Output: Send + 'static
+        {
+            crate::DefaultSpawner::default().spawn(f)
+        }
+
+        fn spawn_on_workers<F>(&self, workers: &[usize], f: F) -> Self::Handle<F::Output>
+        where F: std::future::Future + Send + 'static, F::Output: Send + 'static
+        {
+            self.0.lock().unwrap().push(workers.to_vec());
+            self.spawn(f)
+        }
+
+        fn block_on<F: std::future::Future>(f: F) -> F::Output {
+            <crate::DefaultSpawner as crate::Spawner>::block_on(f)
+        }
+    }
+
+    let spawner = Recording::default();
+    let mut s = unsafe { crate::Scope::create_with(spawner.clone()) }.with_thread_affinity([0, 2]);
+    s.spawn(async { 1 });
+    s.spawn(async { 2 });
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [1, 2]);
+    assert_eq!(*spawner.0.lock().unwrap(), [[0, 2], [0, 2]]);
+
+    // A no-op for the executors of this crate
+    let mut s = unsafe { crate::Scope::create() }.with_thread_affinity([0]);
+    s.spawn(async { 1 });
+    assert_eq!(s.collect().await, [1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_boxed() {
+    use futures::future::BoxFuture;
+
+    let word = String::from("scoped");
+    let word_ref = &word;
+    let queue: Vec<BoxFuture<'_, usize>> = vec![
+        Box::pin(async { 1 }),
+        Box::pin(async move { word_ref.len() }),
+        Box::pin(async {
+            sleep(Duration::from_millis(10)).await;
+            3
+        }),
+    ];
+
+    let mut s = unsafe { crate::Scope::create() };
+    for fut in queue {
+        s.spawn_boxed(fut);
+    }
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [1, 3, 6]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancel_one() {
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..5 {
+        s.spawn_cancellable(async move {
+            sleep(Duration::from_millis(if i == 2 { 10_000 } else { 20 })).await;
+            i
+        }, || 100);
+    }
+    assert!(s.cancel_one(2));
+    assert!(!s.cancel_one(2));
+    assert!(!s.cancel_one(5));
+
+    let mut vals = timeout(Duration::from_secs(1), Box::pin(s.collect())).await.unwrap();
+    vals.sort();
+    assert_eq!(vals, [0, 1, 3, 4, 100]);
+    assert!(!s.cancel_one(0));
+
+    // Uncancellable futures are left alone
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { 1 });
+    assert!(!s.cancel_one(0));
+    assert_eq!(s.collect().await, [1]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn builder() {
+    use crate::{BuildError, DropPolicy};
+
+    let mut s = unsafe { crate::Scope::builder()
+        .concurrency_limit(2)
+        .rate_limit(1000.)
+        .drop_policy(DropPolicy::BlockOnFinish)
+        .ordered()
+        .initial_capacity(8)
+        .build() }.unwrap();
+    assert_eq!(s.concurrency_limit(), Some(2));
+    assert_eq!(s.drop_policy(), DropPolicy::BlockOnFinish);
+    for i in 0..5u64 {
+        s.spawn_limited(async move {
+            sleep(Duration::from_millis(10 * (5 - i))).await;
+            i
+        }).await;
+    }
+    assert_eq!(s.collect().await, [0, 1, 2, 3, 4]);
+
+    let mut s = unsafe { crate::Scope::builder().ordered().build_indexed() }.unwrap();
+    s.spawn(async { 1 });
+    let vals: Vec<_> = futures::StreamExt::collect(&mut s).await;
+    assert_eq!(vals, [(0, 1)]);
+
+    let invalid = |builder: crate::ScopeBuilder<'_, ()>| unsafe { builder.build() }.err();
+    assert_eq!(invalid(crate::Scope::builder().ordered().unordered()),
+               Some(BuildError::ConflictingOrder));
+    assert_eq!(invalid(crate::Scope::builder().ordered().ordered()), None);
+    assert_eq!(invalid(crate::Scope::builder().concurrency_limit(0)),
+               Some(BuildError::ZeroConcurrencyLimit));
+    assert_eq!(invalid(crate::Scope::builder().ordered_window(0)),
+               Some(BuildError::ZeroWindow));
+    assert_eq!(invalid(crate::Scope::builder().rate_limit(f64::NAN)),
+               Some(BuildError::InvalidRateLimit));
+    assert_eq!(invalid(crate::Scope::builder().rate_limit(f64::INFINITY)),
+               Some(BuildError::InvalidRateLimit));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn collect_available() {
+    let mut s = unsafe { crate::Scope::create() };
+    for i in 0..3 {
+        s.spawn(async move { i });
+    }
+    s.spawn(async {
+        sleep(Duration::from_millis(100)).await;
+        3
+    });
+    sleep(Duration::from_millis(20)).await;
+
+    let mut vals = s.collect_available().await;
+    vals.sort();
+    assert_eq!(vals, [0, 1, 2]);
+    assert_eq!(s.remaining(), 1);
+
+    // Spawn more, and keep draining
+    s.spawn(async { 4 });
+    let mut vals = Vec::new();
+    loop {
+        let available = s.collect_available().await;
+        if available.is_empty() { break; }
+        vals.extend(available);
+    }
+    vals.sort();
+    assert_eq!(vals, [3, 4]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drain_tag() {
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async {
+        sleep(Duration::from_millis(100)).await;
+        0
+    });
+    for i in 1..4 {
+        s.spawn_tagged("first", async move {
+            sleep(Duration::from_millis(10 * (4 - i))).await;
+            i
+        });
+    }
+    s.spawn_tagged(String::from("first"), async { 10 });
+    s.spawn_tagged(1, async { 20 });
+
+    assert_eq!(s.drain_tag("first").await, [1, 2, 3]);
+    assert_eq!(s.drain_tag("first").await, Vec::<u64>::new());
+    assert_eq!(s.drain_tag(1).await, [20]);
+    assert_eq!(s.drain_tag(String::from("first")).await, [10]);
+
+    // The untagged futures were left running
+    assert_eq!(s.collect().await, [0]);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn undrained_tags() {
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async { 0 });
+    s.spawn_tagged("first", async {
+        sleep(Duration::from_millis(50)).await;
+        1
+    });
+    s.spawn_tagged("second", async { 2 });
+    s.spawn_tagged("second", async { panic!("tagged") });
+
+    assert_eq!(s.drain_tag("first").await, [1]);
+    let mut outputs: Vec<_> = s.collect_caught().await;
+    assert_eq!(outputs.iter().filter(|output| output.is_err()).count(), 1);
+    outputs.retain(Result::is_ok);
+    let mut vals: Vec<_> = outputs.into_iter().map(Result::unwrap).collect();
+    vals.sort();
+    assert_eq!(vals, [0, 2]);
+    assert_eq!(s.drain_tag("second").await, Vec::<u64>::new());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_with_receiver() {
+    let mut s = unsafe { crate::Scope::create() };
+    s.spawn(async {
+        sleep(Duration::from_millis(100)).await;
+        1
+    });
+    let rx = s.spawn_with_receiver(async { 2 });
+    assert_eq!(s.remaining(), 2);
+    assert_eq!(rx.await, Ok(2));
+
+    // Yielded by the stream once the receiver is dropped
+    std::mem::drop(s.spawn_with_receiver(async {
+        sleep(Duration::from_millis(10)).await;
+        3
+    }));
+    let mut vals = s.collect().await;
+    vals.sort();
+    assert_eq!(vals, [1, 3]);
+    assert_eq!(s.remaining(), 0);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_adaptive_concurrency() {
+    let mut stats = Vec::new();
+    let stats_ref = &mut stats;
+    let mut s = unsafe { crate::Scope::create() }
+        .with_adaptive_concurrency(2, |blocked: crate::BackpressureStats| {
+            stats_ref.push(blocked);
+            (blocked.limit + 1).min(4)
+        });
+    for i in 0..8 {
+        s.spawn_limited(async move {
+            sleep(Duration::from_millis(20)).await;
+            i
+        }).await;
+        assert!(s.in_flight() <= s.concurrency_limit().unwrap());
+    }
+    assert_eq!(s.concurrency_limit(), Some(4));
+    assert_eq!(s.collect().await.len(), 8);
+    std::mem::drop(s);
+
+    assert_eq!(stats[0], crate::BackpressureStats { in_flight: 2, weight: 2, waiting: 1, limit: 2 });
+    assert_eq!(stats[1].limit, 3);
+    assert!(stats[2..].iter().all(|blocked| blocked.limit == 4));
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_fairness() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::Fairness;
+
+    assert_eq!(unsafe { crate::Scope::<()>::create() }.fairness(), Fairness::PreferDrain);
+    for fairness in [Fairness::PreferDrain, Fairness::PreferSpawn] {
+        let running = AtomicUsize::new(0);
+        let running_ref = &running;
+        let mut s = unsafe { crate::Scope::create() }
+            .with_concurrency_limit(2)
+            .with_fairness(fairness);
+        assert_eq!(s.fairness(), fairness);
+        for i in 0..6 {
+            s.spawn_limited(async move {
+                assert!(running_ref.fetch_add(1, Ordering::SeqCst) < 2);
+                sleep(Duration::from_millis(10)).await;
+                running_ref.fetch_sub(1, Ordering::SeqCst);
+                i
+            }).await;
+        }
+        let mut vals = s.collect().await;
+        vals.sort();
+        assert_eq!(vals, (0..6).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn scoped_join_set() {
+    let words = [String::from("scoped"), String::from("join"), String::from("set")];
+    let mut set = unsafe { crate::ScopedJoinSet::new() };
+    for word in &words {
+        set.spawn(async move { word.len() });
+    }
+    let slow = set.spawn(async {
+        sleep(Duration::from_secs(10)).await;
+        0
+    });
+    assert_eq!(set.len(), 4);
+
+    let mut lens = Vec::new();
+    for _ in 0..3 {
+        lens.push(set.join_next().await.unwrap().unwrap());
+    }
+    lens.sort();
+    assert_eq!(lens, [3, 4, 6]);
+
+    slow.abort();
+    assert!(set.join_next().await.unwrap().unwrap_err().is_cancelled());
+    assert!(set.join_next().await.is_none());
+    assert!(set.is_empty());
+
+    set.spawn(async {
+        sleep(Duration::from_secs(10)).await;
+        0
+    });
+    set.shutdown().await;
+    assert!(set.is_empty());
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn scope_into_results() {
+    use futures::StreamExt;
+
+    let words = [String::from("scoped"), String::from("results")];
+    let words_ref = &words;
+    let (count, mut stream) = unsafe { crate::scope_into_results(|s| {
+        for (i, word) in words_ref.iter().enumerate() {
+            s.spawn(async move {
+                sleep(Duration::from_millis(50 * i as u64)).await;
+                word.len()
+            });
+        }
+        words_ref.len()
+    }) };
+    assert_eq!(count, 2);
+
+    // The outputs are pulled one at a time, once the block
+    // has returned.
+    assert_eq!(stream.remaining(), 2);
+    assert_eq!(stream.next().await, Some(6));
+    assert_eq!(stream.remaining(), 1);
+    assert_eq!(stream.next().await, Some(7));
+    assert_eq!(stream.next().await, None);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_dependent() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let started = AtomicUsize::new(0);
+    let started_ref = &started;
+    let mut s = unsafe { crate::Scope::create() };
+    let ok = s.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        1
+    });
+    let failed = s.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        panic!("upstream failed")
+    });
+    let after_ok = s.spawn_dependent(&[&ok], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        2
+    });
+    let after_failed = s.spawn_dependent(&[&ok, &failed], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        3
+    });
+    // Cascades to the dependents of the aborted future
+    let downstream = s.spawn_dependent(&[&after_failed], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        4
+    });
+    for handle in [ok, failed, after_ok, after_failed, downstream] {
+        std::mem::drop(handle);
+    }
+
+    let outputs: Vec<_> = futures::StreamExt::collect(s.caught()).await;
+    let mut vals: Vec<_> = outputs.iter().filter_map(|output| output.as_ref().ok().copied()).collect();
+    vals.sort();
+    assert_eq!(vals, [1, 2]);
+    assert_eq!(outputs.iter().filter(|output| output.is_err()).count(), 1);
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn spawn_dependent_ok() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let started = AtomicUsize::new(0);
+    let started_ref = &started;
+    let mut s = unsafe { crate::Scope::create() };
+    let ok = s.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        Ok(1)
+    });
+    let errored = s.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        Err("upstream failed")
+    });
+    // Already finished when the dependent is spawned
+    let errored_early = s.spawn(async { Err("upstream failed early") });
+    sleep(Duration::from_millis(10)).await;
+    let after_ok = s.spawn_dependent_ok(&[&ok], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        Ok(2)
+    });
+    let after_errored = s.spawn_dependent_ok(&[&ok, &errored], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        Ok(3)
+    });
+    let after_errored_early = s.spawn_dependent_ok(&[&errored_early], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        Ok(4)
+    });
+    // Cascades to the dependents of the aborted future
+    let downstream = s.spawn_dependent(&[&after_errored], async move {
+        started_ref.fetch_add(1, Ordering::SeqCst);
+        Ok(5)
+    });
+    for handle in [ok, errored, errored_early, after_ok, after_errored, after_errored_early, downstream] {
+        std::mem::drop(handle);
+    }
+
+    let mut outputs: Vec<_> = futures::StreamExt::collect(s).await;
+    outputs.sort();
+    assert_eq!(outputs, [Ok(1), Ok(2), Err("upstream failed"), Err("upstream failed early")]);
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn with_panic_hook() {
+    use std::sync::{Arc, Mutex};
+
+    let panics = Arc::new(Mutex::new(Vec::new()));
+    let hook_panics = panics.clone();
+    let mut s = unsafe { crate::Scope::create() }.with_panic_hook(move |index, payload| {
+        let message = payload.downcast_ref::<&str>().copied().unwrap_or_default();
+        hook_panics.lock().unwrap().push((index, message));
+    });
+    s.spawn(async { 0 });
+    s.spawn(async { panic!("boom") });
+    s.spawn(async { 2 });
+
+    let outputs: Vec<_> = futures::StreamExt::collect(s.caught()).await;
+    assert_eq!(outputs.iter().filter(|output| output.is_ok()).count(), 2);
+    assert_eq!(*panics.lock().unwrap(), [(1, "boom")]);
+}
+
+/// Dropping a scope mid-flight drives its futures before
+/// returning.
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn drop_mid_flight() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    let finished = AtomicUsize::new(0);
+    let finished_ref = &finished;
+    let mut s = unsafe { crate::Scope::create() }.with_concurrency_limit(2);
+    for _ in 0..4 {
+        s.spawn_limited(async move {
+            sleep(Duration::from_millis(20)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        }).await;
+    }
+    for _ in 0..4 {
+        s.spawn_cancellable(async move {
+            sleep(Duration::from_secs(10)).await;
+            finished_ref.fetch_add(1, Ordering::SeqCst);
+        }, || ());
+    }
+    assert!(s.in_flight() >= 4);
+    assert_eq!(s.remaining_cancellable(), 4);
+
+    let start = Instant::now();
+    std::mem::drop(s);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    // The limited futures ran to completion, and the
+    // cancellable ones were cancelled.
+    assert_eq!(finished.load(Ordering::SeqCst), 4);
+}
+
+/// This is a simplified version of the soundness bug
+/// pointed out on [reddit][reddit-ref]. Here, we test that
+/// it does not happen when using the `scope_and_collect`,
+/// but the returned future is not forgotten. Forgetting the
+/// future should lead to an invalid memory access.
+///
+/// [reddit-ref]: https://www.reddit.com/r/rust/comments/ee3vsu/asyncscoped_spawn_non_static_futures_with_asyncstd/fbpis3c?utm_source=share&utm_medium=web2x
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn cancellation_soundness() {
+    use std::time::*;
+
+    async fn inner() {
+        let mut shared = true;
+        let shared_ref = &mut shared;
+
+        let start = Instant::now();
+
+        let mut fut = Box::pin(
+            unsafe { crate::scope_and_collect(|scope| {
+                scope.spawn_cancellable(async {
+                    sleep(Duration::from_millis(500)).await;
+
+                    eprintln!("Trying to write to shared_ref");
+                    *shared_ref = false;
+                    assert!(*shared_ref);
+                }, || ());
+            })}
+        );
+        let _ = timeout(Duration::from_millis(10), &mut fut).await;
+
+        // Dropping explicitly to measure time taken to complete drop.
+        // Change the drop to forget for panic due to invalid mem. access.
+        std::mem::drop(fut);
+        let elapsed = start.elapsed().as_millis();
+
+
+        // The cancelled future should have been polled
+        // before the inner large timeout.
+        assert!(elapsed < 100);
+        eprintln!("Elapsed: {}ms", start.elapsed().as_millis());
+    }
+
+    inner().await;
+
+    // This timeout allows any (possible) invalid memory
+    // access to actually take place.
+    sleep(Duration::from_millis(600)).await;
+
+}
+
+/// Same as `cancellation_soundness`, but driven entirely
+/// by smol: the futures are spawned via `SmolSpawner`, and
+/// the test itself runs on `smol::block_on`.
+#[cfg(feature = "smol")]
+#[test]
+fn cancellation_soundness_smol() {
+    use std::time::*;
+    use smol::Timer;
+
+    async fn inner() {
+        let mut shared = true;
+        let shared_ref = &mut shared;
+
+        let start = Instant::now();
+
+        let mut fut = Box::pin(async {
+            let mut scope = unsafe { crate::Scope::create_with(crate::SmolSpawner) };
+            scope.spawn_cancellable(async {
+                Timer::after(Duration::from_millis(500)).await;
+
+                eprintln!("Trying to write to shared_ref");
+                *shared_ref = false;
+                assert!(*shared_ref);
+            }, || ());
+            scope.collect().await
+        });
+        let _ = smol::future::or(
+            async { Some((&mut fut).await) },
+            async { Timer::after(Duration::from_millis(10)).await; None },
+        ).await;
+
+        std::mem::drop(fut);
+        let elapsed = start.elapsed().as_millis();
+
+        assert!(elapsed < 100);
+        eprintln!("Elapsed: {}ms", start.elapsed().as_millis());
+    }
+
+    smol::block_on(async {
+        inner().await;
+        Timer::after(Duration::from_millis(600)).await;
+    });
+}
+
+/// Spawns each future onto a thread of its own. Unlike the
+/// executors, this is supported by Miri, which checks the
+/// futures' accesses to the borrowed data for races.
+#[derive(Debug, Default, Clone, Copy)]
+struct ThreadSpawner;
+
+unsafe impl crate::Spawner for ThreadSpawner {
+    type Handle<T> = futures::future::BoxFuture<'static, T>;
+
+    fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+    where F: std::future::Future + Send + 'static, F::Output: Send + 'static
+    {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(futures::executor::block_on(f));
+        });
+        Box::pin(async move { rx.await.expect("spawned thread panicked") })
+    }
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        futures::executor::block_on(f)
+    }
+}
+
+/// Same as `cancellation_soundness`, but independent of
+/// timing, and driven by `ThreadSpawner`: the stream is
+/// dropped while its futures are pending, and must wait
+/// for them. Run under Miri via:
+///
+/// ``` sh
+/// cargo +nightly miri test cancellation_soundness_threads
+/// ```
+#[test]
+fn cancellation_soundness_threads() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use futures::FutureExt;
+
+    let cancelled = AtomicUsize::new(0);
+    let mut written = 0;
+    {
+        let cancelled = &cancelled;
+        let written = &mut written;
+
+        let mut s = unsafe { crate::Scope::create_with(ThreadSpawner) };
+        for _ in 0..2 {
+            s.spawn_cancellable(futures::future::pending(), move || {
+                cancelled.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        s.spawn(async move {
+            // Yield once, to be pending when dropped
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded { return std::task::Poll::Ready(()); }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }).await;
+            *written += 1;
+        });
+
+        let mut stream = Box::pin(async move { s.collect().await });
+        assert_eq!((&mut stream).now_or_never(), None);
+        std::mem::drop(stream);
+    }
+    assert_eq!(cancelled.load(Ordering::SeqCst), 2);
+    assert_eq!(written, 1);
+}
+
+/// Explores interleavings of spawning, aborting (from a
+/// concurrent task) and draining a scope via shuttle,
+/// checking that the borrowed data is accessed only while
+/// the scope is alive.
+#[cfg(shuttle)]
+#[test]
+fn cancellation_soundness_shuttle() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default, Clone, Copy)]
+    struct ShuttleSpawner;
+
+    unsafe impl crate::Spawner for ShuttleSpawner {
+        type Handle<T> = futures::future::BoxFuture<'static, T>;
+
+        fn spawn<F>(&self, f: F) -> Self::Handle<F::Output>
+        where F: std::future::Future + Send + 'static, F::Output: Send + 'static
+        {
+            let join = shuttle::future::spawn(f);
+            Box::pin(async move { join.await.expect("shuttle task failed") })
+        }
+
+        fn block_on<F: std::future::Future>(f: F) -> F::Output {
+            shuttle::future::block_on(f)
+        }
+    }
+
+    shuttle::check_random(|| shuttle::future::block_on(async {
+        let completed = AtomicUsize::new(0);
+        let cancelled = AtomicUsize::new(0);
+        {
+            let (completed, cancelled) = (&completed, &cancelled);
+
+            let mut s = unsafe { crate::Scope::create_with(ShuttleSpawner) };
+            let aborter = s.abort_handle();
+            for i in 0..3 {
+                s.spawn_cancellable(async move {
+                    for _ in 0..i {
+                        shuttle::future::yield_now().await;
+                    }
+                    completed.fetch_add(1, Ordering::SeqCst);
+                }, move || {
+                    cancelled.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            let abort = shuttle::future::spawn(async move { aborter.abort() });
+            s.spawn(async move {
+                shuttle::future::yield_now().await;
+                completed.fetch_add(1, Ordering::SeqCst);
+            });
+            s.drain().await;
+            abort.await.unwrap();
+        }
+
+        // Every future either completed or was cancelled,
+        // and none of them runs after the scope is done.
+        let (completed, cancelled) = (completed.into_inner(), cancelled.into_inner());
+        assert!(completed >= 1);
+        assert_eq!(completed + cancelled, 4);
+    }), 1000);
+}
+
+/// This test is resource consuming and ignored by default
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+#[ignore]
+async fn backpressure() {
+    let mut s = unsafe { crate::Scope::create() };
+    let limit = 0x10;
+    for i in 0..0x100 {
+        s.spawn(async {
+            // Allocate a large array (256 MB)
+            let blob = vec![42u8; 0x10000000];
+
+            // Spend a lot of time on it asynchronously
+            sleep(Duration::from_millis(100)).await;
+
+            std::mem::drop(blob);
+        });
+
+        while s.remaining() > limit {
+            use futures::StreamExt;
+            s.next().await;
+        }
+        eprintln!("Spawned {} futures", i);
+    }
+}
+
+#[cfg_attr(all(feature = "async-std", not(feature = "tokio")), async_std::test)]
+#[cfg_attr(any(feature = "tokio", not(feature = "async-std")), tokio::test(flavor = "multi_thread"))]
+async fn capacity() {
+    let mut s = unsafe { crate::Scope::create() };
+    let limit = 0x10;
+    let mut drained = 0;
+    for i in 0..0x100 {
+        drained += s.capacity(limit).await.len();
+        s.spawn(async move {
+            // Allocate a (small) array
+            let blob = vec![42u8; 0x1000];
+
+            // Spend some time on it asynchronously
+            sleep(Duration::from_millis(1)).await;
+
+            std::mem::drop(blob);
+            i
+        });
+        assert!(s.remaining() <= limit);
+    }
+    drained += s.collect().await.len();
+    assert_eq!(drained, 0x100);
 }
 
 // Mutability test: should fail to compile.
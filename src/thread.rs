@@ -0,0 +1,74 @@
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::thread::{self, ScopedJoinHandle};
+
+/// A scope to spawn non 'static closures onto OS threads,
+/// without an executor; see [`thread_scope`][thread_scope].
+/// The threads are joined before `thread_scope` returns,
+/// and hence may borrow data outliving the call.
+pub struct ThreadScope<'s, 'env: 's, T> {
+    scope: &'s thread::Scope<'s, 'env>,
+    handles: Mutex<Vec<ScopedJoinHandle<'s, T>>>,
+
+    // Future proof against variance changes
+    _marker: PhantomData<fn(&'env ()) -> &'env ()>
+}
+
+impl<'s, 'env, T: Send + 's> ThreadScope<'s, 'env, T> {
+    /// Spawn `f` onto a new thread. Its output is collected
+    /// by `thread_scope`, in order of spawning.
+    pub fn spawn_thread<F>(&self, f: F)
+    where F: FnOnce() -> T + Send + 's
+    {
+        let handle = self.scope.spawn(f);
+        self.handles.lock().unwrap().push(handle);
+    }
+
+    /// Number of threads spawned so far.
+    pub fn len(&self) -> usize {
+        self.handles.lock().unwrap().len()
+    }
+
+    /// Whether no threads were spawned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Creates a [`ThreadScope`][ThreadScope] to spawn non
+/// 'static closures onto OS threads, similar to
+/// `std::thread::scope` (on which it is built), but
+/// collecting the outputs of the threads as the `scope_and_*`
+/// functions do. No async runtime is needed.
+///
+/// Unlike [`scope`][crate::scope], this is safe: the scope
+/// is only lent to the block, which can not forget it, and
+/// all the threads are joined before this returns.
+///
+/// # Returns
+///
+/// The output of the block, and the outputs of the threads
+/// in order of spawning.
+///
+/// # Panics
+///
+/// Once all the threads are joined, the panic of the first
+/// thread that panicked (in order of spawning), if any, is
+/// resumed. A panic in the block is resumed once the threads
+/// are joined, as in `std::thread::scope`.
+pub fn thread_scope<'env, T: Send + 'env, R, F>(f: F) -> (R, Vec<T>)
+where F: for<'s> FnOnce(&ThreadScope<'s, 'env, T>) -> R
+{
+    thread::scope(|scope| {
+        let s = ThreadScope { scope, handles: Mutex::new(Vec::new()), _marker: PhantomData };
+        let block_output = f(&s);
+        let joined: Vec<_> = s.handles.into_inner().unwrap()
+            .into_iter()
+            .map(ScopedJoinHandle::join)
+            .collect();
+        let outputs = joined.into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|payload| std::panic::resume_unwind(payload));
+        (block_output, outputs)
+    })
+}
@@ -0,0 +1,17 @@
+/// When the duration of a future starts, as yielded by
+/// `Scope::with_timing_from`. Either way, it ends once the
+/// future completes, and excludes the time spent waiting
+/// for the output to be yielded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimingStart {
+    /// The future is timed from its spawn: after waiting
+    /// for the concurrency (or rate) limit, if any, but
+    /// including the time spent waiting for the executor to
+    /// first poll it. This is the default.
+    #[default]
+    Spawn,
+
+    /// The future is timed from its first poll by the
+    /// executor.
+    FirstPoll,
+}
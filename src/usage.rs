@@ -1,4 +1,18 @@
-use crate::Scope;
+use crate::{Scope, Spawner, CaughtPanic};
+
+// Only the functions taking a spawner are available without
+// an executor.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+use {
+    std::collections::HashMap,
+    std::future::Future,
+    std::hash::Hash,
+    futures::future::LocalBoxFuture,
+    std::time::Duration,
+    crate::{ScopeStream, DefaultSpawner, PartialOutputs, LogLine},
+};
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+use crate::LocalScope;
 
 /// Creates a `Scope` to spawn non-'static futures. The
 /// function is called with a block which takes an `&mut
@@ -13,13 +27,22 @@ use crate::Scope;
 /// forgotten. Dropping this stream causes the stream to be
 /// driven _while blocking the current thread_. The values
 /// returned from the stream are the output of the futures
-/// spawned.
+/// spawned. Note that unlike the `scope_and_*` functions,
+/// which return the value of the block first, the stream is
+/// returned first here.
 ///
 /// # Safety
 ///
 /// The returned stream is expected to be run to completion
 /// before being forgotten. Dropping it is okay, but blocks
-/// the current thread until all spawned futures complete.
+/// the current thread until no spawned future is being
+/// driven: by default, the cancellable futures are
+/// cancelled, and the others awaited; to bound the wait,
+/// the others may be aborted instead, via
+/// `Scope::with_drop_policy(DropPolicy::AbortAll)`.
+/// Forgetting the stream (say, via `mem::forget`) skips
+/// this, and is what makes this function unsafe.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
 pub unsafe fn scope<'a, T: Send + 'static, R,
                     F: FnOnce(&mut Scope<'a, T>) -> R
                     >(f: F) -> (Scope<'a, T>, R)
@@ -29,6 +52,28 @@ pub unsafe fn scope<'a, T: Send + 'static, R,
     (scope, op)
 }
 
+/// Similar to [`scope`][scope], but the scope is returned
+/// as a `ScopeStream` of the outputs, after the output of
+/// the block. The stream is owned, and may be consumed
+/// (say, via `StreamExt`) one output at a time once the
+/// block has run; futures can then only be spawned via the
+/// handles obtained within the block.
+///
+/// # Safety
+///
+/// The same considerations as `scope` apply: the stream
+/// must not be forgotten, hence it is `#[must_use]`, and
+/// dropping it drives the scope to completion.
+#[must_use = "dropping the stream blocks until its futures complete"]
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub unsafe fn scope_into_results<'a, T: Send + 'static, R,
+                                 F: FnOnce(&mut Scope<'a, T>) -> R
+                                 >(f: F) -> (R, ScopeStream<'a, T>)
+{
+    let (scope, block_output) = scope(f);
+    (block_output, scope.into_stream())
+}
+
 /// A function that creates a scope and immediately awaits,
 /// _blocking the current thread_ for spawned futures to
 /// complete. The outputs of the futures are collected as a
@@ -49,20 +94,127 @@ pub unsafe fn scope<'a, T: Send + 'static, R,
 /// recursively spawned should have the same lifetime as the
 /// top-level scope, or there should not be any spurious
 /// future cancellations within the top level scope.
+///
+/// # Panics
+///
+/// If a spawned future panics, the panic is resumed on the
+/// current thread once all the other futures have
+/// completed; use [`scope_and_block_caught`][scope_and_block_caught]
+/// to collect the panics instead. Panics that are not
+/// caught by the scope (see `Scope::catch_panics`) are
+/// resumed as soon as the executor surfaces them: the
+/// remaining futures are then driven while unwinding, as
+/// the scope is dropped.
+///
+/// Under `tokio`, panics if called from within the
+/// current-thread runtime, whose thread would have to drive
+/// the futures while blocked by this function. Use
+/// [`scope_and_block_on`][scope_and_block_on] to spawn the
+/// futures elsewhere.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
 pub fn scope_and_block<'a, T: Send + 'static, R,
                        F: FnOnce(&mut Scope<'a, T>) -> R
                        >(f: F) -> (R, Vec<T>)
 {
+    let (block_output, proc_outputs) = scope_and_block_caught(f);
+    (block_output, resume_panics(proc_outputs))
+}
+
+/// Similar to [`scope_and_block`][scope_and_block], but the
+/// futures that panicked are collected as
+/// [`CaughtPanic`][CaughtPanic] errors, as in
+/// [`scope_and_collect_caught`][scope_and_collect_caught].
+///
+/// # Panics
+///
+/// The same considerations as `scope_and_block` apply to
+/// the futures spawned with `Scope::catch_panics` disabled,
+/// and to the current-thread runtime of `tokio`.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub fn scope_and_block_caught<'a, T: Send + 'static, R,
+                              F: FnOnce(&mut Scope<'a, T>) -> R
+                              >(f: F) -> (R, Vec<Result<T, CaughtPanic>>)
+{
+    // Fail before spawning: once spawned, the futures can
+    // neither progress nor be abandoned.
+    #[cfg(feature = "tokio")]
+    crate::spawner::assert_can_block();
+
     let (mut stream, block_output) = unsafe {scope(f)};
-    let proc_outputs = async_std::task::block_on(stream.collect());
+    let proc_outputs = DefaultSpawner::block_on(stream.collect_caught());
     (block_output, proc_outputs)
 }
 
+/// Similar to [`scope_and_block`][scope_and_block], but the
+/// block is asynchronous: it returns a (boxed) future,
+/// which may await the scope, say via `Scope::join_next`,
+/// to apply backpressure while spawning. The block is
+/// driven on the current thread, which is blocked until
+/// both the block and the spawned futures complete,
+/// exactly as in `scope_and_block`. The outputs taken by
+/// the block are not included in the returned `Vec`.
+///
+/// # Panics
+///
+/// The same considerations as `scope_and_block` apply. A
+/// panic in the block is resumed once the spawned futures
+/// are done (or cancelled), as the scope is dropped.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub fn scope_and_block_with<'a, T: Send + 'static, R, F>(f: F) -> (R, Vec<T>)
+where F: for<'s> FnOnce(&'s mut Scope<'a, T>) -> LocalBoxFuture<'s, R>
+{
+    #[cfg(feature = "tokio")]
+    crate::spawner::assert_can_block();
+
+    let mut stream = unsafe { Scope::create() };
+    let (block_output, proc_outputs) = DefaultSpawner::block_on(async {
+        let block_output = f(&mut stream).await;
+        (block_output, stream.collect_caught().await)
+    });
+    (block_output, resume_panics(proc_outputs))
+}
+
+/// Unwrap the outputs, resuming the first panic (if any)
+/// among them.
+fn resume_panics<T>(outputs: Vec<Result<T, CaughtPanic>>) -> Vec<T> {
+    outputs.into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|caught| std::panic::resume_unwind(caught.into_payload()))
+}
+
+/// Similar to [`scope_and_block`][scope_and_block], but the
+/// futures are spawned via `spawner`, and the current
+/// thread is blocked without entering an executor (instead
+/// of via `Spawner::block_on`). Thus, this may be
+/// called from within a single-threaded executor, say the
+/// current-thread runtime of tokio, as long as `spawner`
+/// drives the futures on other threads: the executor of the
+/// current thread is blocked meanwhile, and a `spawner`
+/// that spawns onto it would deadlock.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_block` apply.
+pub fn scope_and_block_on<'a, T: Send + 'static, R, Sp: Spawner,
+                          F: FnOnce(&mut Scope<'a, T, Sp>) -> R
+                          >(spawner: Sp, f: F) -> (R, Vec<T>)
+{
+    let mut stream = unsafe { Scope::create_with(spawner) };
+    let block_output = f(&mut stream);
+    let proc_outputs = crate::spawner::park_on(stream.collect_caught());
+    (block_output, resume_panics(proc_outputs))
+}
+
 /// An asynchronous function that creates a scope and
 /// immediately awaits the stream. The outputs of the
 /// futures are collected as a `Vec` and returned along with
 /// the output of the block.
 ///
+/// The outputs are in order of completion, which need not
+/// be the order in which the futures were spawned; use
+/// [`scope_and_collect_ordered`][scope_and_collect_ordered]
+/// for the latter.
+///
 /// # Safety
 ///
 /// This function is _not completely safe_: please see
@@ -76,6 +228,7 @@ pub fn scope_and_block<'a, T: Send + 'static, R,
 /// spawned futures complete.
 ///
 /// [tests-src]: https://github.com/rmanoka/async-scoped/blob/master/src/tests.rs
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
 pub async unsafe fn scope_and_collect<'a, T: Send + 'static, R,
                                       F: FnOnce(&mut Scope<'a, T>) -> R
                                       >(f: F) -> (R, Vec<T>)
@@ -84,3 +237,456 @@ pub async unsafe fn scope_and_collect<'a, T: Send + 'static, R,
     let proc_outputs = stream.collect().await;
     (block_output, proc_outputs)
 }
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// the outputs are collected into `outputs`, which is
+/// cleared first. This allows reusing the allocation of the
+/// buffer across calls.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_into<'a, T: Send + 'static, R,
+                                           F: FnOnce(&mut Scope<'a, T>) -> R
+                                           >(f: F, outputs: &mut Vec<T>) -> R
+{
+    let (mut stream, block_output) = scope(f);
+    outputs.clear();
+    stream.collect_into(outputs).await;
+    block_output
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// instead of being collected, each output is passed to
+/// `process` as it arrives (in order of completion), and
+/// the returned future awaited before the next output is
+/// taken. Thus, the outputs are processed sequentially, on
+/// the task driving the scope, and are never buffered.
+/// Returns the output of the block once all the futures
+/// are done.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_process<'a, T: Send + 'static, R,
+                                      F: FnOnce(&mut Scope<'a, T>) -> R,
+                                      P: FnMut(T) -> Fut,
+                                      Fut: Future<Output=()>
+                                      >(f: F, mut process: P) -> R
+{
+    let (mut stream, block_output) = scope(f);
+    while let Some(output) = stream.join_next().await {
+        process(output).await;
+    }
+    block_output
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// the outputs are collected in the order the futures were
+/// spawned: the `i`-th entry of the `Vec` is the output of
+/// the `i`-th spawned future (excluding the futures whose
+/// output was awaited via their `ScopeHandle`).
+///
+/// This is backed by `FuturesOrdered` and hence holds back
+/// completed outputs until all the futures spawned before
+/// them complete. When the order is not needed, prefer
+/// `scope_and_collect`, which does not incur this buffering.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_ordered<'a, T: Send + 'static, R,
+                                              F: FnOnce(&mut Scope<'a, T>) -> R
+                                              >(f: F) -> (R, Vec<T>)
+{
+    let mut stream = Scope::create().with_ordering();
+    let block_output = f(&mut stream);
+    let proc_outputs = stream.collect().await;
+    (block_output, proc_outputs)
+}
+
+/// Same as [`scope_and_collect_ordered`][scope_and_collect_ordered],
+/// under the name of its `'static` counterpart,
+/// `futures::future::join_all`: the `i`-th entry of the
+/// `Vec` is the output of the `i`-th spawned future,
+/// whichever completes first.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[inline]
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_join_all<'a, T: Send + 'static, R,
+                                   F: FnOnce(&mut Scope<'a, T>) -> R
+                                   >(f: F) -> (R, Vec<T>)
+{
+    scope_and_collect_ordered(f).await
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// a panic in a spawned future is collected as an `Err`
+/// instead of being resumed. Use this to observe the panics
+/// of the spawned futures without unwinding.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_caught<'a, T: Send + 'static, R,
+                                             F: FnOnce(&mut Scope<'a, T>) -> R
+                                             >(f: F) -> (R, Vec<Result<T, CaughtPanic>>)
+{
+    let (mut stream, block_output) = scope(f);
+    let proc_outputs = stream.collect_caught().await;
+    (block_output, proc_outputs)
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// only the first `n` outputs (in order of completion) are
+/// collected: the cancellable futures are then cancelled.
+/// The returned `Vec` has `n` entries, or fewer if fewer
+/// futures were spawned.
+///
+/// The remaining futures are driven to completion (or
+/// cancelled) before returning, and their outputs are
+/// discarded.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_n<'a, T: Send + 'static, R,
+                                        F: FnOnce(&mut Scope<'a, T>) -> R
+                                        >(n: usize, f: F) -> (R, Vec<T>)
+{
+    let (mut stream, block_output) = scope(f);
+    let mut proc_outputs = Vec::with_capacity(n.min(stream.remaining()));
+    while proc_outputs.len() < n {
+        match stream.join_next().await {
+            Some(output) => proc_outputs.push(output),
+            None => break,
+        }
+    }
+    stream.cancel().await;
+    stream.drain().await;
+    (block_output, proc_outputs)
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// the outputs (in order of completion) are collected only
+/// until one satisfies `pred`: the cancellable futures are
+/// then cancelled. The returned `Vec` ends with the
+/// matching output, if any; otherwise, it holds all the
+/// outputs. `pred` is invoked on the current thread, as
+/// each output arrives.
+///
+/// The remaining futures are driven to completion (or
+/// cancelled) before returning, and their outputs are
+/// discarded.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_until<'a, T: Send + 'static, R,
+                                            F: FnOnce(&mut Scope<'a, T>) -> R,
+                                            P: FnMut(&T) -> bool
+                                            >(f: F, mut pred: P) -> (R, Vec<T>)
+{
+    let (mut stream, block_output) = scope(f);
+    let mut proc_outputs = Vec::new();
+    while let Some(output) = stream.join_next().await {
+        let found = pred(&output);
+        proc_outputs.push(output);
+        if found { break; }
+    }
+    stream.cancel().await;
+    stream.drain().await;
+    (block_output, proc_outputs)
+}
+
+/// Similar to [`scope_and_collect_n`][scope_and_collect_n]
+/// with `n = 1`: the output of the first future to
+/// complete is returned (or `None` if no futures were
+/// spawned), and the other futures are cancelled, or
+/// aborted if not cancellable (as with
+/// `DropPolicy::AbortAll`). This suits racing redundant
+/// requests, where the fastest wins.
+///
+/// For soundness, the futures that can not be aborted,
+/// such as started blocking work, are still driven to
+/// completion before returning, and their outputs are
+/// discarded.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn select_scope<'a, T: Send + 'static, R,
+                                 F: FnOnce(&mut Scope<'a, T>) -> R
+                                 >(f: F) -> (R, Option<T>)
+{
+    let (mut stream, block_output) = scope(f);
+    let winner = stream.join_next().await;
+    stream.cancel().await;
+    stream.abort_pending();
+    stream.drain().await;
+    (block_output, winner)
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// only the outputs that arrive within `timeout` are
+/// collected; see `Scope::collect_timeout`. The pending
+/// cancellable futures are then cancelled, and the
+/// remaining ones driven to completion before returning.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_timeout<'a, T: Send + 'static, R,
+                                              F: FnOnce(&mut Scope<'a, T>) -> R
+                                              >(timeout: Duration, f: F) -> (R, PartialOutputs<T>)
+{
+    let (mut stream, block_output) = scope(f);
+    let proc_outputs = stream.collect_timeout(timeout).await;
+    (block_output, proc_outputs)
+}
+
+/// An asynchronous function that creates a scope of
+/// fallible futures and immediately awaits the stream,
+/// short-circuiting on the first error. If a future
+/// resolves to `Err(e)`, the cancellable futures are
+/// cancelled and `Err(e)` is returned along with the output
+/// of the block; otherwise, the collected outputs are
+/// returned.
+///
+/// The remaining futures are driven to completion (or
+/// cancelled) before returning even on error, and their
+/// outputs are discarded, as by `Scope::drain`.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn try_scope_and_collect<'a, T: Send + 'static,
+                                          E: Send + 'static, R,
+                                          F: FnOnce(&mut Scope<'a, Result<T, E>>) -> R
+                                          >(f: F) -> (R, Result<Vec<T>, E>)
+{
+    use futures::StreamExt;
+
+    let (mut stream, block_output) = scope(f);
+    let mut proc_outputs = Vec::with_capacity(stream.remaining());
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(output) => proc_outputs.push(output),
+            Err(err) => {
+                stream.cancel().await;
+                stream.drain().await;
+                return (block_output, Err(err));
+            }
+        }
+    }
+    (block_output, Ok(proc_outputs))
+}
+
+/// An asynchronous function that creates a scope of
+/// fallible futures and immediately awaits the stream,
+/// partitioning the outputs into the successes and the
+/// failures. Unlike `try_scope_and_collect`, all the futures
+/// are driven to completion. Each error is tagged with the
+/// index (in order of spawning) of the future that
+/// returned it.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_results<'a, T: Send + 'static,
+                                              E: Send + 'static, R,
+                                              F: FnOnce(&mut Scope<'a, Result<T, E>>) -> R
+                                              >(f: F) -> (R, (Vec<T>, Vec<(usize, E)>))
+{
+    use futures::StreamExt;
+
+    let mut stream = Scope::create().with_indexing();
+    let block_output = f(&mut stream);
+    let mut successes = Vec::with_capacity(stream.remaining());
+    let mut failures = vec![];
+    while let Some((index, item)) = stream.next().await {
+        match item {
+            Ok(output) => successes.push(output),
+            Err(err) => failures.push((index, err)),
+        }
+    }
+    (block_output, (successes, failures))
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// the outputs are collected into a map, keyed by
+/// `key(index, &output)`, where `index` is the index (in
+/// order of spawning) of the future. If several outputs
+/// have the same key, the output of the future spawned last
+/// is kept, whichever completes first; use
+/// `scope_and_collect_grouped` to keep all of them.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_map<'a, T: Send + 'static, R, K: Hash + Eq,
+                                          F: FnOnce(&mut Scope<'a, T>) -> R,
+                                          G: FnMut(usize, &T) -> K
+                                          >(f: F, mut key: G) -> (R, HashMap<K, T>)
+{
+    let (block_output, outputs) = collect_sorted(f).await;
+    let mut map = HashMap::with_capacity(outputs.len());
+    for (index, output) in outputs {
+        map.insert(key(index, &output), output);
+    }
+    (block_output, map)
+}
+
+/// Similar to [`scope_and_collect_map`][scope_and_collect_map],
+/// but the outputs with the same key are all kept, in order
+/// of spawning of their futures.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_grouped<'a, T: Send + 'static, R, K: Hash + Eq,
+                                              F: FnOnce(&mut Scope<'a, T>) -> R,
+                                              G: FnMut(usize, &T) -> K
+                                              >(f: F, mut key: G) -> (R, HashMap<K, Vec<T>>)
+{
+    let (block_output, outputs) = collect_sorted(f).await;
+    let mut map: HashMap<K, Vec<T>> = HashMap::new();
+    for (index, output) in outputs {
+        map.entry(key(index, &output)).or_default().push(output);
+    }
+    (block_output, map)
+}
+
+/// Collect the outputs along with their index, sorted by
+/// index.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+async unsafe fn collect_sorted<'a, T: Send + 'static, R,
+                               F: FnOnce(&mut Scope<'a, T>) -> R
+                               >(f: F) -> (R, Vec<(usize, T)>)
+{
+    use futures::StreamExt;
+
+    let mut stream = Scope::create().with_indexing();
+    let block_output = f(&mut stream);
+    let mut outputs: Vec<_> = StreamExt::collect(&mut stream).await;
+    outputs.sort_unstable_by_key(|&(index, _)| index);
+    (block_output, outputs)
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// the lines logged by the futures (spawned via
+/// `Scope::spawn_logged`) are collected too, in the order
+/// they were logged, once all the futures are done.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_and_collect_logged<'a, T: Send + 'static, R,
+                                             F: FnOnce(&mut Scope<'a, T>) -> R
+                                             >(f: F) -> (R, (Vec<T>, Vec<LogLine>))
+{
+    let (mut stream, block_output) = scope(f);
+    let proc_outputs = stream.collect().await;
+    (block_output, (proc_outputs, stream.take_logs()))
+}
+
+/// Similar to `StreamExt::for_each_concurrent`: spawns the
+/// future `f(item)` for each item of `items`, with at most
+/// `limit` of them being driven concurrently, and awaits
+/// them all. Unlike `for_each_concurrent`, the futures are
+/// spawned onto the executor, and may borrow non 'static
+/// data.
+///
+/// # Panics
+///
+/// Panics if `limit` is zero.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_for_each_concurrent<'a, I, F, Fut>(limit: usize, items: I, f: F)
+where I: IntoIterator, F: FnMut(I::Item) -> Fut,
+      Fut: Future<Output=()> + Send + 'a
+{
+    let mut stream = Scope::create().with_concurrency_limit(limit);
+    stream.spawn_all_limited(items.into_iter().map(f)).await;
+    stream.drain().await;
+}
+
+/// Similar to `StreamExt::buffer_unordered`: each item of
+/// `fns` is called to create a future, with at most `limit`
+/// of the futures being driven concurrently, and `f` is
+/// invoked on the output of each (in order of completion),
+/// on the current thread. Unlike `scope_for_each_concurrent`
+/// the futures are created lazily, only once there is room
+/// for them: the futures are spawned onto the executor, and
+/// may borrow non 'static data, but those not yet started
+/// take no memory.
+///
+/// # Panics
+///
+/// Panics if `limit` is zero.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio", feature = "smol"))]
+pub async unsafe fn scope_buffer_unordered<'a, T: Send + 'static, I, G, Fut, F>(
+    limit: usize, fns: I, mut f: F
+)
+where I: IntoIterator<Item=G>, G: FnOnce() -> Fut,
+      Fut: Future<Output=T> + Send + 'a, F: FnMut(T)
+{
+    assert!(limit > 0, "limit must be positive");
+    let mut stream = Scope::create();
+    let mut fns = fns.into_iter().fuse();
+    loop {
+        while stream.remaining() < limit {
+            match fns.next() {
+                Some(g) => { stream.spawn(g()); }
+                None => break,
+            }
+        }
+        match stream.join_next().await {
+            Some(output) => f(output),
+            None => break,
+        }
+    }
+}
+
+/// Similar to [`scope_and_collect`][scope_and_collect], but
+/// creates a `LocalScope`, which allows spawning futures
+/// that are not `Send` via `spawn_local`. This must be
+/// called on a thread running a local executor: under
+/// `tokio`, within a `LocalSet`.
+///
+/// # Safety
+///
+/// The same considerations as `scope_and_collect` apply.
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub async unsafe fn local_scope_and_collect<'a, T: 'static, R,
+                                            F: FnOnce(&mut LocalScope<'a, T>) -> R
+                                            >(f: F) -> (R, Vec<T>)
+{
+    let mut stream = LocalScope::create();
+    let block_output = f(&mut stream);
+    let proc_outputs = stream.collect().await;
+    (block_output, proc_outputs)
+}